@@ -0,0 +1,261 @@
+use crate::ast::{Declaration, Expr, ExprKind, Function, Program, Span, Stmt, StmtKind};
+use crate::symbols::SymbolTable;
+use crate::visitor::{walk_program, Visitor};
+
+/// A use of a variable that is not declared at that point
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UndeclaredVariable {
+    pub name: String,
+    /// The span of the name itself
+    pub span: Span,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Find every variable that is read, indexed or assigned without a declaration in scope. As in C,
+/// a variable has to be declared before its first use, so a declaration later in the same block
+/// does not count. Calls of undeclared functions are not reported.
+///
+/// `text` is the source the program was parsed from and is used to locate the names.
+pub fn undeclared_variables(program: &Program, text: &str) -> Vec<UndeclaredVariable> {
+    let mut pass = Undeclared {
+        symbols: SymbolTable::default(),
+        text,
+        found: vec![],
+    };
+    walk_program(&mut pass, program);
+    pass.found
+}
+
+struct Undeclared<'t> {
+    symbols: SymbolTable,
+    text: &'t str,
+    found: Vec<UndeclaredVariable>,
+}
+
+impl Undeclared<'_> {
+    fn check(&mut self, name: &str, span: Span) {
+        if self.symbols.lookup(name).is_none() {
+            let (line, column) = span.line_column(self.text);
+            self.found.push(UndeclaredVariable {
+                name: name.to_string(),
+                span,
+                line,
+                column,
+            });
+        }
+    }
+
+    /// Check an assignment target, which is the first occurrence of its name in the statement
+    fn check_target(&mut self, name: &str, stmt: &Stmt) {
+        let offset = self.text[stmt.span.start..stmt.span.end]
+            .find(name)
+            .unwrap_or(0);
+        let start = stmt.span.start + offset;
+        let span = Span {
+            start,
+            end: start + name.len(),
+        };
+        self.check(name, span);
+    }
+}
+
+impl Visitor for Undeclared<'_> {
+    fn visit_function(&mut self, function: &Function) {
+        // a function is declared before its body, which allows recursion
+        self.symbols.define_function(function);
+        self.symbols.enter_function(function);
+    }
+
+    fn leave_function(&mut self, _function: &Function) {
+        self.symbols.exit_scope();
+    }
+
+    fn enter_scope(&mut self) {
+        self.symbols.enter_scope();
+    }
+
+    fn leave_scope(&mut self) {
+        self.symbols.exit_scope();
+    }
+
+    /// The initializers are checked by now, the variables come into scope after them
+    fn leave_declaration(&mut self, declaration: &Declaration) {
+        self.symbols.declare(declaration);
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Assign { target, .. } | StmtKind::IncDec { target, .. } => {
+                self.check_target(target, stmt)
+            }
+            _ => {}
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        let name = match &expr.kind {
+            ExprKind::Variable(name) => return self.check(name, expr.span),
+            ExprKind::Index { array: name, .. } | ExprKind::Assign { target: name, .. } => name,
+            _ => return,
+        };
+        let span = Span {
+            start: expr.span.start,
+            end: expr.span.start + name.len(),
+        };
+        self.check(name, span);
+    }
+}
+
+/// Find statements that can never run because an earlier statement of the same block always
+/// returns. A statement always returns if it is a `return`, a block containing one that always
+/// returns, or an `if` whose branches both always return. Loops are assumed to possibly not run
+/// their body at all.
+///
+/// Every unreachable statement is reported with its span, statements nested in it are not
+/// reported separately.
+pub fn unreachable_code(program: &Program) -> Vec<Span> {
+    let mut pass = Unreachable { found: vec![] };
+    walk_program(&mut pass, program);
+    pass.found
+}
+
+struct Unreachable {
+    found: Vec<Span>,
+}
+
+impl Unreachable {
+    fn block(&mut self, statements: &[Stmt]) {
+        let Some(first) = statements.first() else {
+            return;
+        };
+        // the block is part of a statement that is reported already
+        if self
+            .found
+            .iter()
+            .any(|span| span.start <= first.span.start && first.span.end <= span.end)
+        {
+            return;
+        }
+        let mut reachable = true;
+        for stmt in statements {
+            if !reachable {
+                self.found.push(stmt.span);
+            }
+            reachable = reachable && !always_returns(stmt);
+        }
+    }
+}
+
+impl Visitor for Unreachable {
+    fn visit_function(&mut self, function: &Function) {
+        self.block(&function.body);
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        if let StmtKind::Block(statements) = &stmt.kind {
+            self.block(statements);
+        }
+    }
+}
+
+fn always_returns(stmt: &Stmt) -> bool {
+    match &stmt.kind {
+        StmtKind::Return(_) => true,
+        StmtKind::Block(statements) => statements.iter().any(always_returns),
+        StmtKind::If {
+            then_branch,
+            else_branch: Some(else_branch),
+            ..
+        } => always_returns(then_branch) && always_returns(else_branch),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::{undeclared_variables, unreachable_code};
+    use crate::C1Parser;
+
+    /// Return (name, line, column) of every undeclared variable
+    fn undeclared(text: &str) -> Vec<(String, usize, usize)> {
+        let program = C1Parser::parse_to_ast(text).unwrap();
+        undeclared_variables(&program, text)
+            .into_iter()
+            .map(|found| (found.name, found.line, found.column))
+            .collect()
+    }
+
+    #[test]
+    fn undeclared_operand() {
+        let text = "void f() {\n  int x;\n  x = y + 1;\n}";
+        let program = C1Parser::parse_to_ast(text).unwrap();
+        let found = undeclared_variables(&program, text);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].name, "y");
+        assert_eq!((found[0].line, found[0].column), (3, 7));
+        assert_eq!(&text[found[0].span.start..found[0].span.end], "y");
+    }
+
+    #[test]
+    fn targets_and_scopes() {
+        assert_eq!(
+            undeclared("void f() { x = 1; }"),
+            vec![("x".to_string(), 1, 12)]
+        );
+        assert_eq!(
+            undeclared("void f() { ++x; }"),
+            vec![("x".to_string(), 1, 14)]
+        );
+        assert_eq!(undeclared("void f() { int b = a[0]; }").len(), 1);
+        assert_eq!(undeclared("void f() { g(x = 1); }").len(), 1);
+        // declared too late, or in a block that is already closed
+        assert_eq!(undeclared("void f() { x = 1; int x; }").len(), 1);
+        assert_eq!(undeclared("void f() { { int x; } x = 1; }").len(), 1);
+        assert_eq!(
+            undeclared("void f() { for (int i = 0; i < 3; i += 1) {} i = 1; }").len(),
+            1
+        );
+        assert!(undeclared("void f() { for (int i = 0; i < 3; i += 1) { i = 2; } }").is_empty());
+        // parameters, globals and enclosing blocks are in scope
+        assert!(undeclared("int g; void f(int p) { int x; { x = p + g; } }").is_empty());
+        // functions are not variables, but calling an unknown one is fine here
+        assert!(undeclared("void f() { f(); g(); }").is_empty());
+    }
+
+    /// Return the text of every unreachable statement
+    fn unreachable(text: &str) -> Vec<&str> {
+        let program = C1Parser::parse_to_ast(text).unwrap();
+        unreachable_code(&program)
+            .into_iter()
+            .map(|span| &text[span.start..span.end])
+            .collect()
+    }
+
+    #[test]
+    fn after_return() {
+        assert_eq!(
+            unreachable("int f() { int x; return 1; x = 2; }"),
+            vec!["x = 2;"]
+        );
+        assert_eq!(
+            unreachable("void f() { { return; } f(); if (true) {} }"),
+            vec!["f();", "if (true) {}"]
+        );
+        assert_eq!(
+            unreachable("int f() { if (a) return 1; else { return 2; } return 3; }"),
+            vec!["return 3;"]
+        );
+        assert_eq!(
+            unreachable("void f() { while (a) { return; a = 1; } f(); }"),
+            vec!["a = 1;"]
+        );
+    }
+
+    #[test]
+    fn conditional_return() {
+        assert!(unreachable("int f() { if (a) { return 1; } return 2; }").is_empty());
+        assert!(unreachable("int f() { if (a) { } else { return 1; } return 2; }").is_empty());
+        assert!(unreachable("int f() { while (a) { return 1; } return 2; }").is_empty());
+    }
+}
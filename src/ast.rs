@@ -0,0 +1,722 @@
+use crate::C1Token;
+
+/// Byte range of a node in the source text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// Return the 1-based line and column where the span starts in `text`. Like the lexer's
+    /// columns, they count characters.
+    /// ```
+    /// use cb_3::ast::Span;
+    /// assert_eq!(Span { start: 5, end: 6 }.line_column("ab\ncde"), (2, 3));
+    /// ```
+    pub fn line_column(&self, text: &str) -> (usize, usize) {
+        let before = &text[..self.start];
+        let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+        let line = before.matches('\n').count() + 1;
+        (line, before[line_start..].chars().count() + 1)
+    }
+}
+
+/// A whole C1 source file, see `C1Parser::parse_to_ast`
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Program {
+    pub items: Vec<Item>,
+}
+
+/// A top-level definition
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Item {
+    Function(Function),
+    /// A global variable declaration
+    Global(Declaration),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Function {
+    pub return_type: Type,
+    pub name: String,
+    pub parameters: Vec<Parameter>,
+    pub body: Vec<Stmt>,
+    pub span: Span,
+}
+
+/// The types and names a function is declared with, without its body
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Signature {
+    pub return_type: Type,
+    pub parameter_types: Vec<Type>,
+    pub parameter_names: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Parameter {
+    pub ty: Type,
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Type {
+    Bool,
+    Float,
+    Int,
+    Void,
+}
+
+/// `int a, b[3], c = 1;` declares three variables sharing one type
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Declaration {
+    pub ty: Type,
+    pub variables: Vec<Variable>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Variable {
+    pub name: String,
+    /// The size of an array variable, `None` for a scalar
+    pub array_size: Option<usize>,
+    pub initializer: Option<Expr>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum StmtKind {
+    /// `{ ... }`
+    Block(Vec<Stmt>),
+    /// A lone `;`
+    Empty,
+    Declaration(Declaration),
+    If {
+        condition: Expr,
+        then_branch: Box<Stmt>,
+        else_branch: Option<Box<Stmt>>,
+    },
+    While {
+        condition: Expr,
+        body: Box<Stmt>,
+    },
+    DoWhile {
+        body: Box<Stmt>,
+        condition: Expr,
+    },
+    /// `update` is an `Assign` statement if present, `init` may also be a `Declaration` whose
+    /// variables are only in scope in the loop
+    For {
+        init: Option<Box<Stmt>>,
+        condition: Expr,
+        update: Option<Box<Stmt>>,
+        body: Box<Stmt>,
+    },
+    Return(Option<Expr>),
+    Break,
+    Continue,
+    /// The arguments of a printf call, starting with the format
+    Printf(Vec<Expr>),
+    /// The assembly string without its quotes, escapes are not decoded
+    Asm(String),
+    /// `target op value` or `target[index] op value`, where `op` may also be a compound
+    /// assignment like `+=`
+    Assign {
+        target: String,
+        index: Option<Expr>,
+        op: AssignOp,
+        value: Expr,
+    },
+    /// `x++`, `++x`, `x--` or `--x`
+    IncDec {
+        target: String,
+        op: IncDecOp,
+        prefix: bool,
+    },
+    /// An expression evaluated for its side effects; only function calls are accepted here
+    Expr(Expr),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum AssignOp {
+    /// =
+    Assign,
+    /// +=
+    Add,
+    /// -=
+    Sub,
+    /// *=
+    Mul,
+    /// /=
+    Div,
+    /// %=
+    Rem,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum IncDecOp {
+    Increment,
+    Decrement,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum ExprKind {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Char(char),
+    /// A string literal with its escape sequences decoded
+    String(String),
+    Variable(String),
+    /// `array[index]`
+    Index {
+        array: String,
+        index: Box<Expr>,
+    },
+    Call {
+        name: String,
+        arguments: Vec<Expr>,
+    },
+    Unary {
+        op: UnaryOp,
+        operand: Box<Expr>,
+    },
+    Binary {
+        op: BinaryOp,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    /// `condition ? then_value : else_value`
+    Conditional {
+        condition: Box<Expr>,
+        then_value: Box<Expr>,
+        else_value: Box<Expr>,
+    },
+    /// `target = value` used as an expression, as in `a = b = 1`
+    Assign {
+        target: String,
+        value: Box<Expr>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum UnaryOp {
+    /// -
+    Neg,
+    /// +
+    Plus,
+    /// !
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum BinaryOp {
+    /// +
+    Add,
+    /// -
+    Sub,
+    /// *
+    Mul,
+    /// /
+    Div,
+    /// `/` of two `int` operands, see [`tag_divisions`](crate::typecheck::tag_divisions)
+    IntDiv,
+    /// `/` with a `float` operand, see [`tag_divisions`](crate::typecheck::tag_divisions)
+    FloatDiv,
+    /// %
+    Rem,
+    /// ==
+    Equal,
+    /// !=
+    NotEqual,
+    /// <
+    Less,
+    /// >
+    Greater,
+    /// <=
+    LessEqual,
+    /// >=
+    GreaterEqual,
+    /// &&
+    And,
+    /// ||
+    Or,
+    /// &
+    BitAnd,
+    /// |
+    BitOr,
+    /// ^
+    BitXor,
+    /// <<
+    ShiftLeft,
+    /// >>
+    ShiftRight,
+}
+
+impl Program {
+    /// The tokens the tree stands for, in the order of a depth-first walk. For a program without
+    /// redundant parentheses this is the order of the lexer; parentheses are not part of the tree
+    /// and are only emitted where the precedence of the operators requires them. A function
+    /// without parameters is emitted as `()`, never `(void)`.
+    pub fn tokens_in_tree_order(&self) -> Vec<C1Token> {
+        let mut tokens = TreeTokens { tokens: vec![] };
+        for item in &self.items {
+            match item {
+                Item::Function(function) => tokens.function(function),
+                Item::Global(declaration) => tokens.declaration(declaration),
+            }
+        }
+        tokens.tokens
+    }
+}
+
+/// Collects the tokens for `Program::tokens_in_tree_order`
+struct TreeTokens {
+    tokens: Vec<C1Token>,
+}
+
+impl TreeTokens {
+    fn push(&mut self, token: C1Token) {
+        self.tokens.push(token);
+    }
+
+    fn function(&mut self, function: &Function) {
+        self.push(type_token(function.return_type));
+        self.push(C1Token::Identifier);
+        self.push(C1Token::LeftParenthesis);
+        for (i, parameter) in function.parameters.iter().enumerate() {
+            if i > 0 {
+                self.push(C1Token::Comma);
+            }
+            self.push(type_token(parameter.ty));
+            self.push(C1Token::Identifier);
+        }
+        self.push(C1Token::RightParenthesis);
+        self.block(&function.body);
+    }
+
+    fn block(&mut self, statements: &[Stmt]) {
+        self.push(C1Token::LeftBrace);
+        for stmt in statements {
+            self.stmt(stmt);
+        }
+        self.push(C1Token::RightBrace);
+    }
+
+    fn declaration(&mut self, declaration: &Declaration) {
+        self.push(type_token(declaration.ty));
+        for (i, variable) in declaration.variables.iter().enumerate() {
+            if i > 0 {
+                self.push(C1Token::Comma);
+            }
+            self.push(C1Token::Identifier);
+            if variable.array_size.is_some() {
+                self.tokens.extend([
+                    C1Token::LeftBracket,
+                    C1Token::ConstInt,
+                    C1Token::RightBracket,
+                ]);
+            }
+            if let Some(initializer) = &variable.initializer {
+                self.push(C1Token::Assign);
+                self.expr(initializer, ASSIGNMENT);
+            }
+        }
+        self.push(C1Token::Semicolon);
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Block(statements) => self.block(statements),
+            StmtKind::Declaration(declaration) => self.declaration(declaration),
+            StmtKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.push(C1Token::KwIf);
+                self.parenthesized(condition);
+                self.stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.push(C1Token::KwElse);
+                    self.stmt(else_branch);
+                }
+            }
+            StmtKind::While { condition, body } => {
+                self.push(C1Token::KwWhile);
+                self.parenthesized(condition);
+                self.stmt(body);
+            }
+            StmtKind::DoWhile { body, condition } => {
+                self.push(C1Token::KwDo);
+                self.stmt(body);
+                self.push(C1Token::KwWhile);
+                self.parenthesized(condition);
+                self.push(C1Token::Semicolon);
+            }
+            StmtKind::For {
+                init,
+                condition,
+                update,
+                body,
+            } => {
+                self.push(C1Token::KwFor);
+                self.push(C1Token::LeftParenthesis);
+                match init.as_deref() {
+                    // a declaration ends with its own semicolon
+                    Some(Stmt {
+                        kind: StmtKind::Declaration(declaration),
+                        ..
+                    }) => self.declaration(declaration),
+                    init => {
+                        if let Some(init) = init {
+                            self.simple_stmt(init);
+                        }
+                        self.push(C1Token::Semicolon);
+                    }
+                }
+                self.expr(condition, ASSIGNMENT);
+                self.push(C1Token::Semicolon);
+                if let Some(update) = update {
+                    self.simple_stmt(update);
+                }
+                self.push(C1Token::RightParenthesis);
+                self.stmt(body);
+            }
+            StmtKind::Empty => self.push(C1Token::Semicolon),
+            _ => {
+                self.simple_stmt(stmt);
+                self.push(C1Token::Semicolon);
+            }
+        }
+    }
+
+    /// A statement that is followed by a semicolon, without it
+    fn simple_stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Return(value) => {
+                self.push(C1Token::KwReturn);
+                if let Some(value) = value {
+                    self.expr(value, ASSIGNMENT);
+                }
+            }
+            StmtKind::Break => self.push(C1Token::KwBreak),
+            StmtKind::Continue => self.push(C1Token::KwContinue),
+            StmtKind::Printf(arguments) => {
+                self.push(C1Token::KwPrintf);
+                self.arguments(arguments);
+            }
+            StmtKind::Asm(_) => self.tokens.extend([
+                C1Token::KwAsm,
+                C1Token::LeftParenthesis,
+                C1Token::ConstString,
+                C1Token::RightParenthesis,
+            ]),
+            StmtKind::IncDec { op, prefix, .. } => {
+                let op = match op {
+                    IncDecOp::Increment => C1Token::Increment,
+                    IncDecOp::Decrement => C1Token::Decrement,
+                };
+                if *prefix {
+                    self.tokens.extend([op, C1Token::Identifier]);
+                } else {
+                    self.tokens.extend([C1Token::Identifier, op]);
+                }
+            }
+            StmtKind::Assign {
+                index, op, value, ..
+            } => {
+                let op = match op {
+                    AssignOp::Assign => C1Token::Assign,
+                    AssignOp::Add => C1Token::PlusAssign,
+                    AssignOp::Sub => C1Token::MinusAssign,
+                    AssignOp::Mul => C1Token::StarAssign,
+                    AssignOp::Div => C1Token::SlashAssign,
+                    AssignOp::Rem => C1Token::PercentAssign,
+                };
+                self.push(C1Token::Identifier);
+                if let Some(index) = index {
+                    self.push(C1Token::LeftBracket);
+                    self.expr(index, ASSIGNMENT);
+                    self.push(C1Token::RightBracket);
+                }
+                self.push(op);
+                self.expr(value, ASSIGNMENT);
+            }
+            StmtKind::Expr(expr) => self.expr(expr, ASSIGNMENT),
+            StmtKind::Block(_)
+            | StmtKind::Declaration(_)
+            | StmtKind::If { .. }
+            | StmtKind::While { .. }
+            | StmtKind::DoWhile { .. }
+            | StmtKind::For { .. }
+            | StmtKind::Empty => self.stmt(stmt),
+        }
+    }
+
+    fn parenthesized(&mut self, expr: &Expr) {
+        self.push(C1Token::LeftParenthesis);
+        self.expr(expr, ASSIGNMENT);
+        self.push(C1Token::RightParenthesis);
+    }
+
+    fn arguments(&mut self, arguments: &[Expr]) {
+        self.push(C1Token::LeftParenthesis);
+        for (i, argument) in arguments.iter().enumerate() {
+            if i > 0 {
+                self.push(C1Token::Comma);
+            }
+            self.expr(argument, ASSIGNMENT);
+        }
+        self.push(C1Token::RightParenthesis);
+    }
+
+    /// Emit `expr` where the grammar expects a rule of at least precedence `min`, in parentheses
+    /// if it binds looser than that
+    fn expr(&mut self, expr: &Expr, min: u8) {
+        let parenthesize = precedence(expr) < min;
+        if parenthesize {
+            self.push(C1Token::LeftParenthesis);
+        }
+        match &expr.kind {
+            ExprKind::Int(_) => self.push(C1Token::ConstInt),
+            ExprKind::Float(_) => self.push(C1Token::ConstFloat),
+            ExprKind::Bool(_) => self.push(C1Token::ConstBoolean),
+            ExprKind::Char(_) => self.push(C1Token::ConstChar),
+            ExprKind::String(_) => self.push(C1Token::ConstString),
+            ExprKind::Variable(_) => self.push(C1Token::Identifier),
+            ExprKind::Index { index, .. } => {
+                self.tokens
+                    .extend([C1Token::Identifier, C1Token::LeftBracket]);
+                self.expr(index, ASSIGNMENT);
+                self.push(C1Token::RightBracket);
+            }
+            ExprKind::Call { arguments, .. } => {
+                self.push(C1Token::Identifier);
+                self.arguments(arguments);
+            }
+            ExprKind::Unary {
+                op: UnaryOp::Neg,
+                operand,
+            } => match &operand.kind {
+                // "--x" is one token, which the parser splits into two negations
+                ExprKind::Unary {
+                    op: UnaryOp::Neg,
+                    operand: inner,
+                } if operand.span.start == expr.span.start + 1 => {
+                    self.push(C1Token::Decrement);
+                    self.expr(inner, UNARY);
+                }
+                _ => {
+                    self.push(C1Token::Minus);
+                    self.expr(operand, UNARY);
+                }
+            },
+            ExprKind::Unary {
+                op: UnaryOp::Plus,
+                operand,
+            } => {
+                self.push(C1Token::Plus);
+                self.expr(operand, UNARY);
+            }
+            ExprKind::Unary {
+                op: UnaryOp::Not,
+                operand,
+            } => {
+                self.push(C1Token::Not);
+                self.expr(operand, UNARY);
+            }
+            ExprKind::Binary { op, left, right } => {
+                let level = binary_precedence(*op);
+                // comparisons do not chain, the other operators associate to the left
+                let left_min = if level == COMPARISON {
+                    level + 1
+                } else {
+                    level
+                };
+                self.expr(left, left_min);
+                self.push(binary_token(*op));
+                self.expr(right, level + 1);
+            }
+            ExprKind::Conditional {
+                condition,
+                then_value,
+                else_value,
+            } => {
+                self.expr(condition, CONDITIONAL + 1);
+                self.push(C1Token::Question);
+                self.expr(then_value, ASSIGNMENT);
+                self.push(C1Token::Colon);
+                self.expr(else_value, CONDITIONAL);
+            }
+            ExprKind::Assign { value, .. } => {
+                self.tokens.extend([C1Token::Identifier, C1Token::Assign]);
+                self.expr(value, ASSIGNMENT);
+            }
+        }
+        if parenthesize {
+            self.push(C1Token::RightParenthesis);
+        }
+    }
+}
+
+// Precedence of the expression rules of the grammar, from the loosest to the tightest binding
+const ASSIGNMENT: u8 = 0;
+const CONDITIONAL: u8 = 1;
+const COMPARISON: u8 = 7;
+const UNARY: u8 = 11;
+const FACTOR: u8 = 12;
+
+fn precedence(expr: &Expr) -> u8 {
+    match &expr.kind {
+        ExprKind::Assign { .. } => ASSIGNMENT,
+        ExprKind::Conditional { .. } => CONDITIONAL,
+        ExprKind::Binary { op, .. } => binary_precedence(*op),
+        ExprKind::Unary { .. } => UNARY,
+        _ => FACTOR,
+    }
+}
+
+fn binary_precedence(op: BinaryOp) -> u8 {
+    use BinaryOp::*;
+
+    match op {
+        Or => 2,
+        And => 3,
+        BitOr => 4,
+        BitXor => 5,
+        BitAnd => 6,
+        Equal | NotEqual | Less | Greater | LessEqual | GreaterEqual => COMPARISON,
+        ShiftLeft | ShiftRight => 8,
+        Add | Sub => 9,
+        Mul | Div | IntDiv | FloatDiv | Rem => 10,
+    }
+}
+
+fn binary_token(op: BinaryOp) -> C1Token {
+    match op {
+        BinaryOp::Add => C1Token::Plus,
+        BinaryOp::Sub => C1Token::Minus,
+        BinaryOp::Mul => C1Token::Asterisk,
+        BinaryOp::Div | BinaryOp::IntDiv | BinaryOp::FloatDiv => C1Token::Slash,
+        BinaryOp::Rem => C1Token::Percent,
+        BinaryOp::Equal => C1Token::Equal,
+        BinaryOp::NotEqual => C1Token::NotEqual,
+        BinaryOp::Less => C1Token::Less,
+        BinaryOp::Greater => C1Token::Greater,
+        BinaryOp::LessEqual => C1Token::LessEqual,
+        BinaryOp::GreaterEqual => C1Token::GreaterEqual,
+        BinaryOp::And => C1Token::And,
+        BinaryOp::Or => C1Token::Or,
+        BinaryOp::BitAnd => C1Token::BitAnd,
+        BinaryOp::BitOr => C1Token::BitOr,
+        BinaryOp::BitXor => C1Token::BitXor,
+        BinaryOp::ShiftLeft => C1Token::ShiftLeft,
+        BinaryOp::ShiftRight => C1Token::ShiftRight,
+    }
+}
+
+fn type_token(ty: Type) -> C1Token {
+    match ty {
+        Type::Bool => C1Token::KwBoolean,
+        Type::Float => C1Token::KwFloat,
+        Type::Int => C1Token::KwInt,
+        Type::Void => C1Token::KwVoid,
+    }
+}
+
+/// Serialize a program to JSON, with every node tagged with its kind and span
+#[cfg(feature = "serde")]
+pub fn to_json(program: &Program) -> String {
+    serde_json::to_string(program).expect("an AST can always be serialized")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{tokenize, C1Parser, C1Token};
+
+    /// Compare the tokens of the tree parsed from `text` with the tokens lexed from it
+    fn assert_tree_order(text: &str) {
+        let program = C1Parser::parse_to_ast(text).unwrap();
+        let lexed: Vec<C1Token> = tokenize(text)
+            .into_iter()
+            .map(|(token, ..)| token)
+            .collect();
+        assert_eq!(program.tokens_in_tree_order(), lexed, "{}", text);
+    }
+
+    #[test]
+    fn tokens_in_tree_order() {
+        assert_tree_order("int x = 1 + 2;");
+        assert_tree_order("float a[3], b, c = -x * (y - 1) / 2.0;");
+        assert_tree_order(
+            "int f(int a, bool b) {
+                if (a < 0 || !b && !-a && (a & 1) == 1) return a ? --a : (b = true) ? 1 : 2;
+                else { printf(\"%d\", g(a, 'c'), v[0] << 2); }
+                while (b) do { a += 1; ++a; } while (a != 3);
+                for (int i = 0; i <= a; i = i * 2) { break; continue; }
+                for (;true;) ;
+                a[0] += v[a];
+                return;
+            }",
+        );
+
+        // redundant parentheses are not in the tree
+        let program = C1Parser::parse_to_ast("int x = (1);").unwrap();
+        let tokens = program.tokens_in_tree_order();
+        let expected = [
+            C1Token::KwInt,
+            C1Token::Identifier,
+            C1Token::Assign,
+            C1Token::ConstInt,
+            C1Token::Semicolon,
+        ];
+        assert_eq!(tokens, expected);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json() {
+        use crate::ast::to_json;
+
+        let program = C1Parser::parse_to_ast("int f(){return 1;}").unwrap();
+        let json = to_json(&program);
+        assert!(json.contains(r#""name":"f""#), "{}", json);
+        assert!(
+            json.contains(r#"{"kind":{"Return":{"kind":{"Int":1}"#),
+            "{}",
+            json
+        );
+        assert!(json.contains(r#""span":{"start":0,"end":18}"#), "{}", json);
+    }
+}
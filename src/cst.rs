@@ -0,0 +1,104 @@
+use crate::ast::Span;
+use crate::lexer::C1Token;
+use logos::Logos;
+
+/// The kind of an inner node of a `CstNode` tree
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CstKind {
+    /// The whole source text, including trivia before, between and after the top-level items
+    Program,
+    /// A function definition from its first token up to the `}` closing its body
+    FunctionDefinition,
+    /// A global variable declaration up to its `;`
+    Declaration,
+    /// A `{ ... }` block including both braces
+    Block,
+}
+
+/// Lossless concrete syntax tree. Every byte of the source belongs to exactly one token leaf,
+/// whitespace, line breaks and comments included.
+#[derive(Debug, PartialEq, Clone)]
+pub enum CstNode {
+    Node {
+        kind: CstKind,
+        children: Vec<CstNode>,
+    },
+    Token {
+        token: C1Token,
+        text: String,
+    },
+}
+
+impl CstNode {
+    /// Reconstruct the source text covered by this node
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        self.write_text(&mut text);
+        text
+    }
+
+    fn write_text(&self, out: &mut String) {
+        match self {
+            CstNode::Node { children, .. } => {
+                children.iter().for_each(|child| child.write_text(out))
+            }
+            CstNode::Token { text, .. } => out.push_str(text),
+        }
+    }
+}
+
+/// Build the CST of a text that is already known to be a valid program, whose top-level items
+/// have the given kinds and spans in source order
+pub(crate) fn build(text: &str, items: &[(CstKind, Span)]) -> CstNode {
+    let mut stack = vec![(CstKind::Program, vec![])];
+    let mut items = items.iter().peekable();
+    let mut lexer = C1Token::lexer(text);
+
+    while let Some(token) = lexer.next() {
+        let trivia = matches!(
+            token,
+            C1Token::Whitespace | C1Token::Linebreak | C1Token::CComment | C1Token::CPPComment
+        );
+        if !trivia && stack.len() == 1 {
+            if let Some((kind, _)) = items.peek() {
+                stack.push((*kind, vec![]));
+            }
+        }
+        if token == C1Token::LeftBrace {
+            stack.push((CstKind::Block, vec![]));
+        }
+
+        let leaf = CstNode::Token {
+            token,
+            text: lexer.slice().to_string(),
+        };
+        stack.last_mut().unwrap().1.push(leaf);
+
+        if token == C1Token::RightBrace {
+            close(&mut stack);
+        }
+        // the last token of an item ends it
+        let end = lexer.span().end;
+        if stack.len() == 2 && items.next_if(|(_, span)| span.end == end).is_some() {
+            close(&mut stack);
+        }
+    }
+
+    while stack.len() > 1 {
+        close(&mut stack);
+    }
+    let (kind, children) = stack.pop().unwrap();
+    CstNode::Node { kind, children }
+}
+
+/// Turn the innermost open node into a child of its parent
+fn close(stack: &mut Vec<(CstKind, Vec<CstNode>)>) {
+    if stack.len() > 1 {
+        let (kind, children) = stack.pop().unwrap();
+        stack
+            .last_mut()
+            .unwrap()
+            .1
+            .push(CstNode::Node { kind, children });
+    }
+}
@@ -0,0 +1,116 @@
+use crate::lexer::C1Token;
+use std::error::Error;
+use std::fmt;
+
+/// A syntax error found by the parser. `Display` prints the human-readable `message`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub message: String,
+    /// Line of the offending token, or of the last token at the end of the input
+    pub line: usize,
+    /// Column of the offending token, `None` at the end of the input
+    pub column: Option<usize>,
+    /// Tokens that would have been accepted instead of `found`
+    pub expected: Vec<C1Token>,
+    /// The offending token, `None` at the end of the input
+    pub found: Option<C1Token>,
+}
+
+impl ParseError {
+    /// Append a note on the enclosing rule, like ", in functioncall", to the message
+    pub(crate) fn context(mut self, context: &str) -> Self {
+        self.message.push_str(context);
+        self
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Together with the standard library's blanket `From` implementation, this lets `?` turn a
+/// `ParseError` into a `Box<dyn Error>`
+impl Error for ParseError {}
+
+/// Render `err` like a compiler diagnostic: the line of `source` it occurred in, prefixed with
+/// its number, and a `^` under the offending token. Tabs in front of the token are kept in the
+/// caret line, so the caret stays aligned however wide the terminal displays them. At the end of
+/// the input the caret points just past the last character of the line.
+pub fn render_diagnostic(source: &str, err: &ParseError) -> String {
+    let line = source
+        .lines()
+        .nth(err.line.saturating_sub(1))
+        .unwrap_or_default();
+    let column = err.column.unwrap_or(line.chars().count() + 1);
+    let indent: String = line
+        .chars()
+        .take(column.saturating_sub(1))
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let number = err.line.to_string();
+    let gutter = " ".repeat(number.len());
+
+    format!("{} | {}\n{} | {}^", number, line, gutter, indent)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::{render_diagnostic, ParseError};
+    use crate::C1Parser;
+    use std::error::Error;
+
+    #[test]
+    fn caret_under_token() {
+        let source = "int x;\n\tint y = (1 +;";
+        let err = C1Parser::parse(source).unwrap_err();
+        assert_eq!(
+            render_diagnostic(source, &err),
+            "2 | \tint y = (1 +;\n  | \t           ^"
+        );
+
+        // the gutter grows with the line number
+        let source = "\n\n\n\n\n\n\n\n\nint f( {}";
+        let err = C1Parser::parse(source).unwrap_err();
+        assert_eq!(
+            render_diagnostic(source, &err),
+            "10 | int f( {}\n   |        ^"
+        );
+
+        // at the end of the input the caret follows the last token
+        let source = "void f() {\n  return;";
+        let err = C1Parser::parse(source).unwrap_err();
+        assert_eq!(
+            render_diagnostic(source, &err),
+            "2 |   return;\n  |          ^"
+        );
+
+        // a column of 0 is treated like the first one
+        let err = ParseError {
+            message: String::new(),
+            line: 1,
+            column: Some(0),
+            expected: vec![],
+            found: None,
+        };
+        assert_eq!(render_diagnostic("int x", &err), "1 | int x\n  | ^");
+    }
+
+    fn parse_boxed(text: &str) -> Result<(), Box<dyn Error>> {
+        C1Parser::parse(text)?;
+        Ok(())
+    }
+
+    #[test]
+    fn boxed_error() {
+        assert!(parse_boxed("int f() { return 1; }").is_ok());
+        let err = parse_boxed("int f() { return 1 }").unwrap_err();
+        assert!(
+            err.to_string().contains("in function definition"),
+            "{}",
+            err
+        );
+        assert_eq!(err.downcast_ref::<ParseError>().unwrap().column, Some(20));
+    }
+}
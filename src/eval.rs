@@ -0,0 +1,207 @@
+use crate::ast::{BinaryOp, Expr, ExprKind, UnaryOp};
+use std::fmt;
+
+/// The value of an evaluated expression
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{}", value),
+            Value::Bool(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// Evaluate an expression that only consists of literals and operators. An `int` operand is
+/// converted to `float` when the other operand is a `float`, other mixed operands are an error,
+/// just like division by zero and integer overflow.
+///
+/// ```
+/// use cb_3::eval::{eval_const, Value};
+/// use cb_3::C1Parser;
+///
+/// let program = C1Parser::parse_to_ast("int x = (1 + 2) * 3;").unwrap();
+/// let cb_3::ast::Item::Global(global) = &program.items[0] else { unreachable!() };
+/// let value = eval_const(global.variables[0].initializer.as_ref().unwrap());
+/// assert_eq!(value, Ok(Value::Int(9)));
+/// ```
+pub fn eval_const(expr: &Expr) -> Result<Value, String> {
+    match &expr.kind {
+        ExprKind::Int(value) => Ok(Value::Int(*value)),
+        ExprKind::Float(value) => Ok(Value::Float(*value)),
+        ExprKind::Bool(value) => Ok(Value::Bool(*value)),
+        ExprKind::Unary { op, operand } => unary_op(*op, eval_const(operand)?),
+        // && and || only evaluate their right operand if it decides the result
+        ExprKind::Binary {
+            op: op @ (BinaryOp::And | BinaryOp::Or),
+            left,
+            right,
+        } => match (eval_const(left)?, op) {
+            (Value::Bool(false), BinaryOp::And) => Ok(Value::Bool(false)),
+            (Value::Bool(true), BinaryOp::Or) => Ok(Value::Bool(true)),
+            (left, op) => binary_op(*op, left, eval_const(right)?),
+        },
+        ExprKind::Binary { op, left, right } => {
+            binary_op(*op, eval_const(left)?, eval_const(right)?)
+        }
+        ExprKind::Conditional {
+            condition,
+            then_value,
+            else_value,
+        } => match eval_const(condition)? {
+            Value::Bool(true) => eval_const(then_value),
+            Value::Bool(false) => eval_const(else_value),
+            value => Err(format!("Condition must be a bool, found {:?}", value)),
+        },
+        _ => Err(format!("{:?} is not a constant expression", expr.kind)),
+    }
+}
+
+/// Apply a unary operator to an evaluated operand
+pub(crate) fn unary_op(op: UnaryOp, operand: Value) -> Result<Value, String> {
+    match (op, operand) {
+        (UnaryOp::Neg, Value::Int(value)) => value
+            .checked_neg()
+            .map(Value::Int)
+            .ok_or_else(|| "Integer overflow".to_string()),
+        (UnaryOp::Neg, Value::Float(value)) => Ok(Value::Float(-value)),
+        (UnaryOp::Plus, Value::Int(_) | Value::Float(_)) => Ok(operand),
+        (UnaryOp::Not, Value::Bool(value)) => Ok(Value::Bool(!value)),
+        _ => Err(format!("Cannot apply {:?} to {:?}", op, operand)),
+    }
+}
+
+/// Apply a binary operator to two evaluated operands. `&&` and `||` are evaluated strictly here.
+pub(crate) fn binary_op(op: BinaryOp, left: Value, right: Value) -> Result<Value, String> {
+    use BinaryOp::*;
+
+    let mismatch = || {
+        Err(format!(
+            "Cannot apply {:?} to {:?} and {:?}",
+            op, left, right
+        ))
+    };
+    match (left, right) {
+        (Value::Int(l), Value::Int(r)) => {
+            let result = match op {
+                Add => l.checked_add(r),
+                Sub => l.checked_sub(r),
+                Mul => l.checked_mul(r),
+                Div | IntDiv | Rem if r == 0 => return Err("Division by zero".to_string()),
+                Div | IntDiv => l.checked_div(r),
+                Rem => l.checked_rem(r),
+                BitAnd => Some(l & r),
+                BitOr => Some(l | r),
+                BitXor => Some(l ^ r),
+                ShiftLeft => u32::try_from(r).ok().and_then(|r| l.checked_shl(r)),
+                ShiftRight => u32::try_from(r).ok().and_then(|r| l.checked_shr(r)),
+                And | Or | FloatDiv => return mismatch(),
+                _ => return Ok(Value::Bool(compare(op, l.cmp(&r)))),
+            };
+            result
+                .map(Value::Int)
+                .ok_or_else(|| "Integer overflow".to_string())
+        }
+        (Value::Int(_) | Value::Float(_), Value::Int(_) | Value::Float(_)) => {
+            let (l, r) = (as_float(left), as_float(right));
+            match op {
+                Add => Ok(Value::Float(l + r)),
+                Sub => Ok(Value::Float(l - r)),
+                Mul => Ok(Value::Float(l * r)),
+                Div | FloatDiv if r == 0.0 => Err("Division by zero".to_string()),
+                Div | FloatDiv => Ok(Value::Float(l / r)),
+                Equal => Ok(Value::Bool(l == r)),
+                NotEqual => Ok(Value::Bool(l != r)),
+                Less => Ok(Value::Bool(l < r)),
+                Greater => Ok(Value::Bool(l > r)),
+                LessEqual => Ok(Value::Bool(l <= r)),
+                GreaterEqual => Ok(Value::Bool(l >= r)),
+                _ => mismatch(),
+            }
+        }
+        (Value::Bool(l), Value::Bool(r)) => match op {
+            And => Ok(Value::Bool(l && r)),
+            Or => Ok(Value::Bool(l || r)),
+            Equal => Ok(Value::Bool(l == r)),
+            NotEqual => Ok(Value::Bool(l != r)),
+            _ => mismatch(),
+        },
+        _ => mismatch(),
+    }
+}
+
+fn as_float(value: Value) -> f64 {
+    match value {
+        Value::Int(value) => value as f64,
+        Value::Float(value) => value,
+        Value::Bool(_) => unreachable!("bool is not a number"),
+    }
+}
+
+fn compare(op: BinaryOp, ordering: std::cmp::Ordering) -> bool {
+    match op {
+        BinaryOp::Equal => ordering.is_eq(),
+        BinaryOp::NotEqual => ordering.is_ne(),
+        BinaryOp::Less => ordering.is_lt(),
+        BinaryOp::Greater => ordering.is_gt(),
+        BinaryOp::LessEqual => ordering.is_le(),
+        BinaryOp::GreaterEqual => ordering.is_ge(),
+        _ => unreachable!("{:?} is not a comparison", op),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::Item;
+    use crate::eval::{eval_const, Value};
+    use crate::C1Parser;
+
+    fn eval(expr: &str) -> Result<Value, String> {
+        let text = format!("int x = {};", expr);
+        let program = C1Parser::parse_to_ast(&text).unwrap();
+        let Item::Global(global) = &program.items[0] else {
+            panic!("{:?}", program)
+        };
+        eval_const(global.variables[0].initializer.as_ref().unwrap())
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(eval("2 + 3 * 4"), Ok(Value::Int(14)));
+        assert_eq!(eval("(2 + 3) * 4"), Ok(Value::Int(20)));
+        assert_eq!(eval("7 % 3 - -1"), Ok(Value::Int(2)));
+        assert_eq!(eval("1.0 / 2.0"), Ok(Value::Float(0.5)));
+        assert_eq!(eval("1 / 2.0"), Ok(Value::Float(0.5)));
+        assert_eq!(eval("1 / 2"), Ok(Value::Int(0)));
+        assert_eq!(eval("1 << 4"), Ok(Value::Int(16)));
+    }
+
+    #[test]
+    fn logic_and_comparison() {
+        assert_eq!(eval("true && false"), Ok(Value::Bool(false)));
+        assert_eq!(eval("false || !false"), Ok(Value::Bool(true)));
+        assert_eq!(eval("(1 < 2.5) == true"), Ok(Value::Bool(true)));
+        assert_eq!(eval("3 >= 4 ? 1 : 2"), Ok(Value::Int(2)));
+        // the right operand is never evaluated
+        assert_eq!(eval("false && 1 / 0 == 1"), Ok(Value::Bool(false)));
+    }
+
+    #[test]
+    fn errors() {
+        assert!(eval("1/0").is_err());
+        assert!(eval("1 % 0").is_err());
+        assert!(eval("1.0 / 0").is_err());
+        assert!(eval("true + 1").is_err());
+        assert!(eval("1 && true").is_err());
+        assert!(eval("1.5 % 2").is_err());
+        assert!(eval("9223372036854775807 + 1").is_err());
+        assert!(eval("y + 1").is_err());
+    }
+}
@@ -0,0 +1,152 @@
+use crate::ast::{BinaryOp, Expr, ExprKind, Program};
+use crate::eval::{eval_const, Value};
+use crate::visitor::{walk_program_mut, VisitorMut};
+
+/// Replace constant subexpressions with their value, like `2 + 3` with `5`. `&&`, `||` and `?:`
+/// with a constant left operand or condition are reduced to the operand they evaluate to, so
+/// `true && x` becomes `x`. Expressions that fail to evaluate, like `1 / 0`, are left unchanged.
+pub fn fold_constants(program: &mut Program) {
+    walk_program_mut(&mut Folder, program);
+}
+
+/// Folds every expression after its operands, so they are literals already if they are constant
+struct Folder;
+
+impl VisitorMut for Folder {
+    fn leave_expr(&mut self, expr: &mut Expr) {
+        match &mut expr.kind {
+            ExprKind::Binary { op, left, right } => {
+                // an undecided && or || evaluates to its right operand
+                if let (BinaryOp::And, ExprKind::Bool(true))
+                | (BinaryOp::Or, ExprKind::Bool(false)) = (*op, &left.kind)
+                {
+                    let right = std::mem::replace(&mut **right, literal(Value::Bool(false)));
+                    expr.kind = right.kind;
+                    return;
+                }
+            }
+            ExprKind::Conditional {
+                condition,
+                then_value,
+                else_value,
+            } => {
+                let branch = match condition.kind {
+                    ExprKind::Bool(true) => then_value,
+                    ExprKind::Bool(false) => else_value,
+                    _ => return,
+                };
+                expr.kind = std::mem::replace(&mut **branch, literal(Value::Bool(false))).kind;
+                return;
+            }
+            _ => {}
+        }
+        // the operands are folded already, so anything else is not constant
+        let constant = match &expr.kind {
+            ExprKind::Unary { operand, .. } => is_literal(operand),
+            ExprKind::Binary { left, right, .. } => is_literal(left) && is_literal(right),
+            _ => false,
+        };
+        if !constant {
+            return;
+        }
+        if let Ok(value) = eval_const(expr) {
+            expr.kind = literal(value).kind;
+        }
+    }
+}
+
+fn is_literal(expr: &Expr) -> bool {
+    matches!(
+        expr.kind,
+        ExprKind::Int(_) | ExprKind::Float(_) | ExprKind::Bool(_)
+    )
+}
+
+fn literal(value: Value) -> Expr {
+    let kind = match value {
+        Value::Int(value) => ExprKind::Int(value),
+        Value::Float(value) => ExprKind::Float(value),
+        Value::Bool(value) => ExprKind::Bool(value),
+    };
+    Expr {
+        kind,
+        span: Default::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{ExprKind, Item, StmtKind};
+    use crate::fold::fold_constants;
+    use crate::C1Parser;
+
+    /// Fold `int x = <expr>;` and return the initializer
+    fn fold(expr: &str) -> ExprKind {
+        let text = format!("int x = {};", expr);
+        let mut program = C1Parser::parse_to_ast(&text).unwrap();
+        fold_constants(&mut program);
+        let Item::Global(global) = &mut program.items[0] else {
+            panic!("{:?}", program)
+        };
+        global.variables[0].initializer.take().unwrap().kind
+    }
+
+    #[test]
+    fn literals() {
+        assert_eq!(fold("2 + 3"), ExprKind::Int(5));
+        assert_eq!(fold("2 + 3 * 4"), ExprKind::Int(14));
+        assert_eq!(fold("1 / 2"), ExprKind::Int(0));
+        assert_eq!(fold("1 / 2.0"), ExprKind::Float(0.5));
+        assert_eq!(fold("!(1 < 2) || 3 != 3"), ExprKind::Bool(false));
+        assert_eq!(fold("false ? 1 : 2"), ExprKind::Int(2));
+    }
+
+    #[test]
+    fn partial() {
+        assert_eq!(fold("true && x"), ExprKind::Variable("x".to_string()));
+        assert_eq!(
+            fold("false || (1 < 2 && x)"),
+            ExprKind::Variable("x".to_string())
+        );
+        assert_eq!(fold("1 == 1 ? y : z"), ExprKind::Variable("y".to_string()));
+
+        let ExprKind::Binary { left, right, .. } = fold("x * (2 + 3)") else {
+            panic!()
+        };
+        assert_eq!(left.kind, ExprKind::Variable("x".to_string()));
+        assert_eq!(right.kind, ExprKind::Int(5));
+        // the operand x is still evaluated
+        assert!(matches!(fold("x && true"), ExprKind::Binary { .. }));
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(fold("1 / 0"), ExprKind::Binary { .. }));
+        assert!(matches!(fold("true + 1"), ExprKind::Binary { .. }));
+        assert!(matches!(fold("-(1 < 2)"), ExprKind::Unary { .. }));
+    }
+
+    #[test]
+    fn statements() {
+        let text = "void f() { while (1 > 2) { g(3 * 3); } }";
+        let mut program = C1Parser::parse_to_ast(text).unwrap();
+        fold_constants(&mut program);
+        let Item::Function(function) = &program.items[0] else {
+            panic!("{:?}", program)
+        };
+        let StmtKind::While { condition, body } = &function.body[0].kind else {
+            panic!("{:?}", function)
+        };
+        assert_eq!(condition.kind, ExprKind::Bool(false));
+        let StmtKind::Block(statements) = &body.kind else {
+            panic!("{:?}", body)
+        };
+        let StmtKind::Expr(call) = &statements[0].kind else {
+            panic!("{:?}", statements)
+        };
+        let ExprKind::Call { arguments, .. } = &call.kind else {
+            panic!("{:?}", call)
+        };
+        assert_eq!(arguments[0].kind, ExprKind::Int(9));
+    }
+}
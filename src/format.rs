@@ -0,0 +1,78 @@
+/// One component of a printf format string
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum FormatSpec {
+    /// Text that is printed as is
+    Literal(String),
+    /// A conversion like `%d`, `%f` or `%s`, identified by its conversion character
+    Specifier(char),
+    /// The escaped percent sign `%%`, which does not consume an argument
+    Percent,
+}
+
+/// Split a printf format string into literal fragments and `%` specifiers. A trailing `%` without
+/// a conversion character is kept as literal text.
+pub fn parse_printf_format(s: &str) -> Vec<FormatSpec> {
+    let mut specs = vec![];
+    let mut literal = String::new();
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            literal.push(c);
+            continue;
+        }
+        let spec = match chars.next() {
+            Some('%') => FormatSpec::Percent,
+            Some(conversion) => FormatSpec::Specifier(conversion),
+            None => {
+                literal.push('%');
+                break;
+            }
+        };
+        if !literal.is_empty() {
+            specs.push(FormatSpec::Literal(std::mem::take(&mut literal)));
+        }
+        specs.push(spec);
+    }
+
+    if !literal.is_empty() {
+        specs.push(FormatSpec::Literal(literal));
+    }
+    specs
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::format::{parse_printf_format, FormatSpec};
+
+    #[test]
+    fn specifiers_and_literals() {
+        assert_eq!(
+            parse_printf_format("%d items: %s"),
+            vec![
+                FormatSpec::Specifier('d'),
+                FormatSpec::Literal(" items: ".to_string()),
+                FormatSpec::Specifier('s'),
+            ]
+        );
+    }
+
+    #[test]
+    fn percent_and_edge_cases() {
+        assert_eq!(
+            parse_printf_format("100%% at %f\n"),
+            vec![
+                FormatSpec::Literal("100".to_string()),
+                FormatSpec::Percent,
+                FormatSpec::Literal(" at ".to_string()),
+                FormatSpec::Specifier('f'),
+                FormatSpec::Literal("\n".to_string()),
+            ]
+        );
+        assert_eq!(parse_printf_format(""), vec![]);
+        assert_eq!(
+            parse_printf_format("50%"),
+            vec![FormatSpec::Literal("50%".to_string())]
+        );
+    }
+}
@@ -0,0 +1,110 @@
+use crate::lexer::{C1Token, TokenCategory};
+use logos::Logos;
+
+/// Render `text` as HTML with every token wrapped in a `<span>` whose class names its kind:
+/// `tok-keyword`, `tok-literal`, `tok-operator`, `tok-punctuation`, `tok-identifier`,
+/// `tok-comment` or `tok-error` for input that cannot be lexed. Whitespace and line breaks are
+/// copied unchanged, so the result is meant to be placed in a `<pre>` element.
+///
+/// ```
+/// assert_eq!(
+///     cb_3::highlight_html("x<1"),
+///     concat!(
+///         r#"<span class="tok-identifier">x</span>"#,
+///         r#"<span class="tok-operator">&lt;</span>"#,
+///         r#"<span class="tok-literal">1</span>"#
+///     )
+/// );
+/// ```
+pub fn highlight_html(text: &str) -> String {
+    let mut html = String::with_capacity(text.len() * 2);
+    let mut lexer = C1Token::lexer(text);
+
+    while let Some(token) = lexer.next() {
+        match class(token) {
+            Some(class) => {
+                html.push_str(r#"<span class=""#);
+                html.push_str(class);
+                html.push_str(r#"">"#);
+                escape_into(lexer.slice(), &mut html);
+                html.push_str("</span>");
+            }
+            None => escape_into(lexer.slice(), &mut html),
+        }
+    }
+    html
+}
+
+/// The CSS class of a token, `None` for whitespace and line breaks
+fn class(token: C1Token) -> Option<&'static str> {
+    let class = match token.category() {
+        TokenCategory::Keyword => "tok-keyword",
+        TokenCategory::Operator => "tok-operator",
+        TokenCategory::Punctuation => "tok-punctuation",
+        TokenCategory::IntLiteral
+        | TokenCategory::FloatLiteral
+        | TokenCategory::BoolLiteral
+        | TokenCategory::StringLiteral => "tok-literal",
+        TokenCategory::Identifier => "tok-identifier",
+        TokenCategory::Comment => "tok-comment",
+        TokenCategory::Error => "tok-error",
+        TokenCategory::Whitespace => return None,
+    };
+    Some(class)
+}
+
+/// Append `text` to `html` with the characters that are special in HTML escaped
+fn escape_into(text: &str, html: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            c => html.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::highlight::highlight_html;
+
+    #[test]
+    fn snippet() {
+        let text = "int f() {\n\t// a < b\n  return a && \"<b>\"; /* & */\n}";
+        let expected = [
+            r#"<span class="tok-keyword">int</span> "#,
+            r#"<span class="tok-identifier">f</span>"#,
+            r#"<span class="tok-punctuation">(</span>"#,
+            r#"<span class="tok-punctuation">)</span> "#,
+            r#"<span class="tok-punctuation">{</span>"#,
+            "\n\t",
+            r#"<span class="tok-comment">// a &lt; b</span>"#,
+            "\n  ",
+            r#"<span class="tok-keyword">return</span> "#,
+            r#"<span class="tok-identifier">a</span> "#,
+            r#"<span class="tok-operator">&amp;&amp;</span> "#,
+            r#"<span class="tok-literal">&quot;&lt;b&gt;&quot;</span>"#,
+            r#"<span class="tok-punctuation">;</span> "#,
+            r#"<span class="tok-comment">/* &amp; */</span>"#,
+            "\n",
+            r#"<span class="tok-punctuation">}</span>"#,
+        ];
+        assert_eq!(highlight_html(text), expected.concat());
+    }
+
+    #[test]
+    fn invalid_input() {
+        assert_eq!(
+            highlight_html("$'x"),
+            concat!(
+                r#"<span class="tok-error">$</span>"#,
+                r#"<span class="tok-error">&#39;</span>"#,
+                r#"<span class="tok-identifier">x</span>"#
+            )
+        );
+        assert_eq!(highlight_html(""), "");
+    }
+}
@@ -0,0 +1,493 @@
+use crate::ast::{
+    AssignOp, BinaryOp, Declaration, Expr, ExprKind, Function, IncDecOp, Item, Program, Stmt,
+    StmtKind, Type,
+};
+use crate::eval::{binary_op, unary_op, Value};
+use crate::format::{parse_printf_format, FormatSpec};
+use std::collections::HashMap;
+use std::io::Write;
+
+/// Nesting depth of function calls at which execution is aborted, so that runaway recursion fails
+/// with an error instead of overflowing the native stack
+const MAX_CALL_DEPTH: usize = 128;
+
+/// Run the `main` function of a program and return the value it returns. Global variables are
+/// initialized first, in the order of their declaration. Everything printed with `printf` is
+/// written to `out`.
+///
+/// ```
+/// use cb_3::eval::Value;
+/// use cb_3::interpreter::run;
+/// use cb_3::C1Parser;
+///
+/// let program = C1Parser::parse_to_ast(r#"int main() { printf("hi"); return 3; }"#).unwrap();
+/// let mut out = vec![];
+/// assert_eq!(run(&program, &mut out), Ok(Some(Value::Int(3))));
+/// assert_eq!(out, b"hi");
+/// ```
+pub fn run(program: &Program, out: &mut dyn Write) -> Result<Option<Value>, String> {
+    let mut interpreter = Interpreter {
+        functions: HashMap::new(),
+        globals: HashMap::new(),
+        frames: vec![],
+        out,
+    };
+    for item in &program.items {
+        match item {
+            Item::Function(function) => {
+                interpreter.functions.insert(&function.name, function);
+            }
+            Item::Global(declaration) => interpreter.declare(declaration)?,
+        }
+    }
+    interpreter.call("main", vec![])
+}
+
+/// How a statement finished executing
+enum Flow {
+    Normal,
+    Break,
+    Continue,
+    Return(Option<Value>),
+}
+
+/// A variable together with its declared type
+type Slot = (Type, Value);
+
+struct Interpreter<'p, 'o> {
+    functions: HashMap<&'p str, &'p Function>,
+    globals: HashMap<String, Slot>,
+    /// The scopes of all active function calls, innermost last
+    frames: Vec<Vec<HashMap<String, Slot>>>,
+    out: &'o mut dyn Write,
+}
+
+impl<'p, 'o> Interpreter<'p, 'o> {
+    fn call(&mut self, name: &str, arguments: Vec<Value>) -> Result<Option<Value>, String> {
+        let function = *self
+            .functions
+            .get(name)
+            .ok_or_else(|| format!("Undefined function '{}'", name))?;
+        if arguments.len() != function.parameters.len() {
+            return Err(format!(
+                "Function '{}' takes {} arguments but {} were given",
+                name,
+                function.parameters.len(),
+                arguments.len()
+            ));
+        }
+        if self.frames.len() == MAX_CALL_DEPTH {
+            return Err(format!("Call stack overflow in function '{}'", name));
+        }
+
+        let mut scope = HashMap::new();
+        for (parameter, value) in function.parameters.iter().zip(arguments) {
+            let value = convert(parameter.ty, value)?;
+            scope.insert(parameter.name.clone(), (parameter.ty, value));
+        }
+        self.frames.push(vec![scope]);
+        let flow = self.statements(&function.body);
+        self.frames.pop();
+
+        let value = match flow? {
+            Flow::Return(value) => value,
+            Flow::Normal => None,
+            Flow::Break | Flow::Continue => {
+                return Err(format!("break or continue outside of a loop in '{}'", name))
+            }
+        };
+        match (function.return_type, value) {
+            (Type::Void, None) => Ok(None),
+            (Type::Void, Some(_)) => Err(format!("Void function '{}' returned a value", name)),
+            (ty, Some(value)) => convert(ty, value).map(Some),
+            (_, None) => Err(format!("Function '{}' did not return a value", name)),
+        }
+    }
+
+    fn scopes(&mut self) -> &mut Vec<HashMap<String, Slot>> {
+        self.frames
+            .last_mut()
+            .expect("statements run inside a function")
+    }
+
+    /// Run statements in a new scope
+    fn statements(&mut self, statements: &[Stmt]) -> Result<Flow, String> {
+        self.scopes().push(HashMap::new());
+        let mut flow = Ok(Flow::Normal);
+        for stmt in statements {
+            flow = self.statement(stmt);
+            if !matches!(flow, Ok(Flow::Normal)) {
+                break;
+            }
+        }
+        self.scopes().pop();
+        flow
+    }
+
+    fn statement(&mut self, stmt: &Stmt) -> Result<Flow, String> {
+        match &stmt.kind {
+            StmtKind::Block(statements) => return self.statements(statements),
+            StmtKind::Empty => {}
+            StmtKind::Declaration(declaration) => self.declare(declaration)?,
+            StmtKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                if self.condition(condition)? {
+                    return self.statement(then_branch);
+                } else if let Some(else_branch) = else_branch {
+                    return self.statement(else_branch);
+                }
+            }
+            StmtKind::While { condition, body } => {
+                while self.condition(condition)? {
+                    match self.statement(body)? {
+                        Flow::Break => break,
+                        Flow::Return(value) => return Ok(Flow::Return(value)),
+                        Flow::Normal | Flow::Continue => {}
+                    }
+                }
+            }
+            StmtKind::DoWhile { body, condition } => loop {
+                match self.statement(body)? {
+                    Flow::Break => break,
+                    Flow::Return(value) => return Ok(Flow::Return(value)),
+                    Flow::Normal | Flow::Continue => {}
+                }
+                if !self.condition(condition)? {
+                    break;
+                }
+            },
+            StmtKind::For {
+                init,
+                condition,
+                update,
+                body,
+            } => {
+                // a variable declared in the init is only visible in the loop
+                self.scopes().push(HashMap::new());
+                let flow = self.for_loop(init.as_deref(), condition, update.as_deref(), body);
+                self.scopes().pop();
+                return flow;
+            }
+            StmtKind::Return(value) => {
+                let value = value.as_ref().map(|value| self.value(value)).transpose()?;
+                return Ok(Flow::Return(value));
+            }
+            StmtKind::Break => return Ok(Flow::Break),
+            StmtKind::Continue => return Ok(Flow::Continue),
+            StmtKind::Printf(arguments) => self.printf(arguments)?,
+            StmtKind::Asm(_) => return Err("Inline assembly cannot be interpreted".to_string()),
+            StmtKind::Assign {
+                target,
+                index: Some(_),
+                ..
+            } => return Err(format!("Array '{}' cannot be interpreted", target)),
+            StmtKind::Assign {
+                target,
+                index: None,
+                op,
+                value,
+            } => {
+                let value = self.value(value)?;
+                let value = match op {
+                    AssignOp::Assign => value,
+                    AssignOp::Add => binary_op(BinaryOp::Add, self.load(target)?, value)?,
+                    AssignOp::Sub => binary_op(BinaryOp::Sub, self.load(target)?, value)?,
+                    AssignOp::Mul => binary_op(BinaryOp::Mul, self.load(target)?, value)?,
+                    AssignOp::Div => binary_op(BinaryOp::Div, self.load(target)?, value)?,
+                    AssignOp::Rem => binary_op(BinaryOp::Rem, self.load(target)?, value)?,
+                };
+                self.store(target, value)?;
+            }
+            StmtKind::IncDec { target, op, .. } => {
+                let op = match op {
+                    IncDecOp::Increment => BinaryOp::Add,
+                    IncDecOp::Decrement => BinaryOp::Sub,
+                };
+                let value = binary_op(op, self.load(target)?, Value::Int(1))?;
+                self.store(target, value)?;
+            }
+            StmtKind::Expr(expr) => {
+                self.expr(expr)?;
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    /// Run a for loop, in the scope its caller opened for variables declared in `init`
+    fn for_loop(
+        &mut self,
+        init: Option<&Stmt>,
+        condition: &Expr,
+        update: Option<&Stmt>,
+        body: &Stmt,
+    ) -> Result<Flow, String> {
+        if let Some(init) = init {
+            self.statement(init)?;
+        }
+        while self.condition(condition)? {
+            match self.statement(body)? {
+                Flow::Break => break,
+                Flow::Return(value) => return Ok(Flow::Return(value)),
+                Flow::Normal | Flow::Continue => {}
+            }
+            if let Some(update) = update {
+                self.statement(update)?;
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    /// Define the variables of a declaration in the innermost scope, or as globals outside of
+    /// any function
+    fn declare(&mut self, declaration: &Declaration) -> Result<(), String> {
+        for variable in &declaration.variables {
+            if variable.array_size.is_some() {
+                return Err(format!("Array '{}' cannot be interpreted", variable.name));
+            }
+            let value = match &variable.initializer {
+                Some(initializer) => convert(declaration.ty, self.value(initializer)?)?,
+                None => match declaration.ty {
+                    Type::Bool => Value::Bool(false),
+                    Type::Float => Value::Float(0.0),
+                    Type::Int => Value::Int(0),
+                    Type::Void => {
+                        return Err(format!("Variable '{}' cannot be void", variable.name))
+                    }
+                },
+            };
+            let slot = (declaration.ty, value);
+            match self.frames.last_mut() {
+                Some(scopes) => scopes
+                    .last_mut()
+                    .unwrap()
+                    .insert(variable.name.clone(), slot),
+                None => self.globals.insert(variable.name.clone(), slot),
+            };
+        }
+        Ok(())
+    }
+
+    fn slot(&mut self, name: &str) -> Result<&mut Slot, String> {
+        let local = self.frames.last_mut().and_then(|scopes| {
+            scopes
+                .iter_mut()
+                .rev()
+                .find_map(|scope| scope.get_mut(name))
+        });
+        match local {
+            Some(slot) => Ok(slot),
+            None => self
+                .globals
+                .get_mut(name)
+                .ok_or_else(|| format!("Undefined variable '{}'", name)),
+        }
+    }
+
+    fn load(&mut self, name: &str) -> Result<Value, String> {
+        self.slot(name).map(|(_, value)| *value)
+    }
+
+    fn store(&mut self, name: &str, value: Value) -> Result<Value, String> {
+        let (ty, slot) = self.slot(name)?;
+        *slot = convert(*ty, value)?;
+        Ok(*slot)
+    }
+
+    fn condition(&mut self, expr: &Expr) -> Result<bool, String> {
+        match self.value(expr)? {
+            Value::Bool(value) => Ok(value),
+            value => Err(format!("Condition must be a bool, found {:?}", value)),
+        }
+    }
+
+    /// Evaluate an expression that has to produce a value
+    fn value(&mut self, expr: &Expr) -> Result<Value, String> {
+        self.expr(expr)?
+            .ok_or_else(|| format!("{:?} does not have a value", expr.kind))
+    }
+
+    /// Evaluate an expression, which is `None` for a call of a void function
+    fn expr(&mut self, expr: &Expr) -> Result<Option<Value>, String> {
+        let value = match &expr.kind {
+            ExprKind::Int(value) => Value::Int(*value),
+            ExprKind::Float(value) => Value::Float(*value),
+            ExprKind::Bool(value) => Value::Bool(*value),
+            ExprKind::Variable(name) => self.load(name)?,
+            ExprKind::Call { name, arguments } => {
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| self.value(argument))
+                    .collect::<Result<_, _>>()?;
+                return self.call(name, arguments);
+            }
+            ExprKind::Unary { op, operand } => unary_op(*op, self.value(operand)?)?,
+            ExprKind::Binary {
+                op: op @ (BinaryOp::And | BinaryOp::Or),
+                left,
+                right,
+            } => match (self.value(left)?, op) {
+                (Value::Bool(false), BinaryOp::And) => Value::Bool(false),
+                (Value::Bool(true), BinaryOp::Or) => Value::Bool(true),
+                (left, op) => binary_op(*op, left, self.value(right)?)?,
+            },
+            ExprKind::Binary { op, left, right } => {
+                binary_op(*op, self.value(left)?, self.value(right)?)?
+            }
+            ExprKind::Conditional {
+                condition,
+                then_value,
+                else_value,
+            } => {
+                if self.condition(condition)? {
+                    self.value(then_value)?
+                } else {
+                    self.value(else_value)?
+                }
+            }
+            ExprKind::Assign { target, value } => {
+                let value = self.value(value)?;
+                self.store(target, value)?
+            }
+            ExprKind::Char(_) | ExprKind::String(_) | ExprKind::Index { .. } => {
+                return Err(format!("{:?} cannot be interpreted", expr.kind))
+            }
+        };
+        Ok(Some(value))
+    }
+
+    /// Print a single value, or fill a format string literal with the remaining arguments
+    fn printf(&mut self, arguments: &[Expr]) -> Result<(), String> {
+        let mut text = String::new();
+        match &arguments[0].kind {
+            ExprKind::String(format) => {
+                let mut values = arguments[1..].iter();
+                for spec in parse_printf_format(format) {
+                    match spec {
+                        FormatSpec::Literal(literal) => text.push_str(&literal),
+                        FormatSpec::Percent => text.push('%'),
+                        FormatSpec::Specifier(conversion) => {
+                            let argument = values
+                                .next()
+                                .ok_or_else(|| format!("Missing argument for %{}", conversion))?;
+                            let value = self.value(argument)?;
+                            text.push_str(&format_value(conversion, value)?);
+                        }
+                    }
+                }
+            }
+            _ if arguments.len() == 1 => text = self.value(&arguments[0])?.to_string(),
+            _ => return Err("The first of several printf arguments must be a format".to_string()),
+        }
+        self.out
+            .write_all(text.as_bytes())
+            .map_err(|err| format!("Could not write output: {}", err))
+    }
+}
+
+/// Convert a value to the given type, `int` converts to `float` implicitly
+fn convert(ty: Type, value: Value) -> Result<Value, String> {
+    match (ty, value) {
+        (Type::Int, Value::Int(_)) | (Type::Float, Value::Float(_)) => Ok(value),
+        (Type::Bool, Value::Bool(_)) => Ok(value),
+        (Type::Float, Value::Int(value)) => Ok(Value::Float(value as f64)),
+        _ => Err(format!("Cannot convert {:?} to {:?}", value, ty)),
+    }
+}
+
+fn format_value(conversion: char, value: Value) -> Result<String, String> {
+    match (conversion, value) {
+        ('d' | 'i', Value::Int(value)) => Ok(value.to_string()),
+        ('f', Value::Float(value)) => Ok(format!("{:.6}", value)),
+        ('f', Value::Int(value)) => Ok(format!("{:.6}", value as f64)),
+        ('s', value) => Ok(value.to_string()),
+        _ => Err(format!("Cannot print {:?} with %{}", value, conversion)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::Value;
+    use crate::interpreter::run;
+    use crate::C1Parser;
+
+    fn output(text: &str) -> Result<String, String> {
+        let program = C1Parser::parse_to_ast(text).map_err(|err| err.to_string())?;
+        let mut out = vec![];
+        run(&program, &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn factorial() {
+        let text = r#"
+            int factorial(int n) {
+                if (n <= 1) {
+                    return 1;
+                }
+                return n * factorial(n - 1);
+            }
+
+            void main() {
+                int i = 0;
+                while (i < 6) {
+                    printf("%d! = %d\n", i, factorial(i));
+                    i++;
+                }
+            }"#;
+        assert_eq!(
+            output(text).unwrap(),
+            "0! = 1\n1! = 1\n2! = 2\n3! = 6\n4! = 24\n5! = 120\n"
+        );
+    }
+
+    #[test]
+    fn globals_scopes_and_loops() {
+        let text = r#"
+            int counter = 10;
+            float half = 1 / 2.0;
+            void bump() { counter += 5; }
+            int main() {
+                int sum = 0;
+                for (i = 0; i < 10; i += 1) {
+                    if (i == 3) { continue; }
+                    if (i == 6) { break; }
+                    sum = sum + i;
+                }
+                { int counter = 0; counter++; }
+                bump();
+                printf("%d %d %f %s", sum, counter, half, counter > 12);
+                return sum;
+            }"#;
+        let program = C1Parser::parse_to_ast(text).unwrap();
+        let mut out = vec![];
+        // i is not declared in main
+        assert!(run(&program, &mut out).is_err());
+
+        let text = text.replace("int sum = 0;", "int sum = 0; int i;");
+        assert_eq!(output(&text).unwrap(), "12 15 0.500000 true");
+        let program = C1Parser::parse_to_ast(&text).unwrap();
+        assert_eq!(run(&program, &mut vec![]), Ok(Some(Value::Int(12))));
+
+        // a variable declared in the init only lives as long as the loop
+        let text = text
+            .replace("int i;", "")
+            .replace("for (i = 0;", "for (int i = 0;");
+        assert_eq!(output(&text).unwrap(), "12 15 0.500000 true");
+        let text = text.replace("bump();", "bump(); i = 1;");
+        assert!(output(&text).is_err());
+    }
+
+    #[test]
+    fn runtime_errors() {
+        assert!(output("void f() {}").is_err());
+        assert!(output("void main() { f(); }").is_err());
+        assert!(output("int f(int a) { return a; } void main() { f(); }").is_err());
+        assert!(output("void main() { int x = true; }").is_err());
+        assert!(output("void main() { printf(1 / 0); }").is_err());
+        assert!(output("void main() { main(); }").is_err());
+        assert!(output("int f() { } void main() { printf(f()); }").is_err());
+    }
+}
@@ -1,10 +1,30 @@
+use crate::json;
 use logos::{Lexer, Logos};
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::Range;
 
-#[derive(Logos, Debug, PartialEq, Copy, Clone)]
+#[derive(Logos, Debug, PartialEq, Eq, Hash, Copy, Clone)]
 pub enum C1Token {
+    #[token("asm")]
+    KwAsm,
+
+    /// GCC-style `__attribute__`, only accepted by `C1Parser::parse_with_gcc_attributes`
+    #[token("__attribute__")]
+    KwAttribute,
+
     #[token("bool")]
     KwBoolean,
 
+    #[token("break")]
+    KwBreak,
+
+    #[token("const")]
+    KwConst,
+
+    #[token("continue")]
+    KwContinue,
+
     #[token("do")]
     KwDo,
 
@@ -41,16 +61,48 @@ pub enum C1Token {
     #[token("-")]
     Minus,
 
+    #[token("++")]
+    /// ++
+    Increment,
+
+    #[token("--")]
+    /// --
+    Decrement,
+
     #[token("*")]
     Asterisk,
 
     #[token("/")]
     Slash,
 
+    #[token("%")]
+    /// %
+    Percent,
+
     #[token("=")]
     /// =
     Assign,
 
+    #[token("+=")]
+    /// +=
+    PlusAssign,
+
+    #[token("-=")]
+    /// -=
+    MinusAssign,
+
+    #[token("*=")]
+    /// *=
+    StarAssign,
+
+    #[token("/=")]
+    /// /=
+    SlashAssign,
+
+    #[token("%=")]
+    /// %=
+    PercentAssign,
+
     #[token("==")]
     /// ==
     Equal,
@@ -59,6 +111,10 @@ pub enum C1Token {
     /// !=
     NotEqual,
 
+    #[token("!")]
+    /// !
+    Not,
+
     #[token("<")]
     /// <
     Less,
@@ -75,6 +131,14 @@ pub enum C1Token {
     /// >=
     GreaterEqual,
 
+    #[token("<<")]
+    /// <<
+    ShiftLeft,
+
+    #[token(">>")]
+    /// >>
+    ShiftRight,
+
     #[token("&&")]
     /// &&
     And,
@@ -83,9 +147,27 @@ pub enum C1Token {
     /// ||
     Or,
 
+    #[token("&")]
+    /// &
+    BitAnd,
+
+    #[token("|")]
+    /// |
+    BitOr,
+
+    #[token("^")]
+    /// ^
+    BitXor,
+
     #[token(",")]
     Comma,
 
+    #[token("?")]
+    Question,
+
+    #[token(":")]
+    Colon,
+
     #[token(";")]
     Semicolon,
 
@@ -105,30 +187,48 @@ pub enum C1Token {
     /// }
     RightBrace,
 
-    #[regex("[0-9]+")]
+    #[token("[")]
+    /// [
+    LeftBracket,
+
+    #[token("]")]
+    /// ]
+    RightBracket,
+
+    // single underscores may separate digits: `1_000`
+    #[regex("[0-9](_?[0-9])*")]
+    // a bare `0x` without digits is reported as an error instead of `0` followed by `x`
+    #[regex("0[xX][0-9a-fA-F]*", |lex| lex.slice().len() > 2)]
+    // digits are matched loosely so that `0b2` becomes one error token
+    #[regex("0[bB][0-9]*", valid_binary_literal)]
     ConstInt,
 
-    #[regex(r"(\d+\.\d+)|(\.\d+([eE]([-+])?\d+)?)|(\d+[eE]([-+])?\d+)")]
+    #[regex(r"(\d(_?\d)*\.\d(_?\d)*)|(\.\d(_?\d)*([eE]([-+])?\d(_?\d)*)?)|(\d(_?\d)*[eE]([-+])?\d(_?\d)*)")]
     ConstFloat,
 
     #[regex("true|false")]
     ConstBoolean,
 
-    #[regex("\"[^\n\"]*\"")]
+    // a backslash escapes the following character, so `\"` does not end the literal
+    #[regex(r#""([^\n"\\]|\\[^\n])*""#)]
     ConstString,
 
+    #[regex(r"'([^'\\\n]|\\[nt\\'])'")]
+    ConstChar,
+
     #[regex("[a-zA-Z]+[0-9a-zA-Z]*")]
     Identifier,
 
-    #[regex(r"/\*[^\*/]*\*/", logos::skip)]
+    // Comments and whitespace are real logos tokens so that raw lexing preserves trivia;
+    // C1Lexer skips them in lex_all.
+    #[token("/*", block_comment)]
     CComment,
 
-    #[regex("//[^\n]*(\n)?", logos::skip)]
+    // the line break is left to the Linebreak token so that the line number advances
+    #[regex("//[^\n]*")]
     CPPComment,
 
-    // We can also use this variant to define whitespace,
-    // or any other matches we wish to skip.
-    #[regex(r"[ \t\f]+", logos::skip)]
+    #[regex(r"[ \t\f]+")]
     Whitespace,
 
     #[regex(r"(\r\n|\r|\n)")]
@@ -140,28 +240,371 @@ pub enum C1Token {
     Error,
 }
 
+/// Kind of a token for semantic highlighting, see `C1Token::category`
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum TokenCategory {
+    Keyword,
+    Operator,
+    /// Parentheses, braces, brackets, commas and semicolons
+    Punctuation,
+    IntLiteral,
+    FloatLiteral,
+    BoolLiteral,
+    /// String and char literals
+    StringLiteral,
+    Identifier,
+    Comment,
+    /// Whitespace and line breaks
+    Whitespace,
+    /// Input that cannot be lexed
+    Error,
+}
+
+impl C1Token {
+    /// Classify the token for semantic highlighting
+    /// ```
+    /// use cb_3::{C1Token, TokenCategory};
+    ///
+    /// assert_eq!(C1Token::KwWhile.category(), TokenCategory::Keyword);
+    /// assert_eq!(C1Token::LessEqual.category(), TokenCategory::Operator);
+    /// ```
+    pub fn category(&self) -> TokenCategory {
+        match self {
+            C1Token::KwAsm
+            | C1Token::KwAttribute
+            | C1Token::KwBoolean
+            | C1Token::KwBreak
+            | C1Token::KwConst
+            | C1Token::KwContinue
+            | C1Token::KwDo
+            | C1Token::KwElse
+            | C1Token::KwFloat
+            | C1Token::KwFor
+            | C1Token::KwIf
+            | C1Token::KwInt
+            | C1Token::KwPrintf
+            | C1Token::KwReturn
+            | C1Token::KwVoid
+            | C1Token::KwWhile => TokenCategory::Keyword,
+            C1Token::Comma
+            | C1Token::Semicolon
+            | C1Token::LeftParenthesis
+            | C1Token::RightParenthesis
+            | C1Token::LeftBrace
+            | C1Token::RightBrace
+            | C1Token::LeftBracket
+            | C1Token::RightBracket => TokenCategory::Punctuation,
+            C1Token::ConstInt => TokenCategory::IntLiteral,
+            C1Token::ConstFloat => TokenCategory::FloatLiteral,
+            C1Token::ConstBoolean => TokenCategory::BoolLiteral,
+            C1Token::ConstString | C1Token::ConstChar => TokenCategory::StringLiteral,
+            C1Token::Identifier => TokenCategory::Identifier,
+            C1Token::CComment | C1Token::CPPComment => TokenCategory::Comment,
+            C1Token::Whitespace | C1Token::Linebreak => TokenCategory::Whitespace,
+            C1Token::Error => TokenCategory::Error,
+            _ => TokenCategory::Operator,
+        }
+    }
+
+    /// Human-readable name of the token for error messages: its source spelling for keywords and
+    /// punctuation, a description in angle brackets for tokens with variable text
+    pub fn name(&self) -> &'static str {
+        match self {
+            C1Token::KwAsm => "asm",
+            C1Token::KwAttribute => "__attribute__",
+            C1Token::KwBoolean => "bool",
+            C1Token::KwBreak => "break",
+            C1Token::KwConst => "const",
+            C1Token::KwContinue => "continue",
+            C1Token::KwDo => "do",
+            C1Token::KwElse => "else",
+            C1Token::KwFloat => "float",
+            C1Token::KwFor => "for",
+            C1Token::KwIf => "if",
+            C1Token::KwInt => "int",
+            C1Token::KwPrintf => "printf",
+            C1Token::KwReturn => "return",
+            C1Token::KwVoid => "void",
+            C1Token::KwWhile => "while",
+            C1Token::Plus => "+",
+            C1Token::Minus => "-",
+            C1Token::Increment => "++",
+            C1Token::Decrement => "--",
+            C1Token::Asterisk => "*",
+            C1Token::Slash => "/",
+            C1Token::Percent => "%",
+            C1Token::Assign => "=",
+            C1Token::PlusAssign => "+=",
+            C1Token::MinusAssign => "-=",
+            C1Token::StarAssign => "*=",
+            C1Token::SlashAssign => "/=",
+            C1Token::PercentAssign => "%=",
+            C1Token::Equal => "==",
+            C1Token::NotEqual => "!=",
+            C1Token::Not => "!",
+            C1Token::Less => "<",
+            C1Token::Greater => ">",
+            C1Token::LessEqual => "<=",
+            C1Token::GreaterEqual => ">=",
+            C1Token::ShiftLeft => "<<",
+            C1Token::ShiftRight => ">>",
+            C1Token::And => "&&",
+            C1Token::Or => "||",
+            C1Token::BitAnd => "&",
+            C1Token::BitOr => "|",
+            C1Token::BitXor => "^",
+            C1Token::Comma => ",",
+            C1Token::Question => "?",
+            C1Token::Colon => ":",
+            C1Token::Semicolon => ";",
+            C1Token::LeftParenthesis => "(",
+            C1Token::RightParenthesis => ")",
+            C1Token::LeftBrace => "{",
+            C1Token::RightBrace => "}",
+            C1Token::LeftBracket => "[",
+            C1Token::RightBracket => "]",
+            C1Token::ConstInt => "<integer>",
+            C1Token::ConstFloat => "<float>",
+            C1Token::ConstBoolean => "<boolean>",
+            C1Token::ConstString => "<string>",
+            C1Token::ConstChar => "<char>",
+            C1Token::Identifier => "<identifier>",
+            C1Token::CComment | C1Token::CPPComment => "<comment>",
+            C1Token::Whitespace => "<whitespace>",
+            C1Token::Linebreak => "<line break>",
+            C1Token::Error => "<invalid token>",
+        }
+    }
+}
+
+impl fmt::Display for C1Token {
+    /// Write the name of the token, like `+` for `Plus` or `<identifier>` for `Identifier`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Extend a block comment up to and including the first `*/`, so lone `*` and `/` are allowed
+/// inside. Logos regexes cannot express "anything but `*/`" reliably, hence the callback. An
+/// unterminated comment becomes an Error token.
+fn block_comment(lex: &mut Lexer<C1Token>) -> bool {
+    match lex.remainder().find("*/") {
+        Some(end) => {
+            lex.bump(end + 2);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Accept `0b` literals only if at least one digit follows and all digits are binary
+fn valid_binary_literal(lex: &mut Lexer<C1Token>) -> bool {
+    let digits = &lex.slice()[2..];
+    !digits.is_empty() && digits.bytes().all(|b| b == b'0' || b == b'1')
+}
+
 pub struct C1Lexer<'a> {
-    logos_lexer: Lexer<'a, C1Token>,
-    logos_line_number: usize,
-    current_token: Option<TokenData<'a>>,
-    past: Vec<TokenData<'a>>,
-    marks: usize,
+    /// All tokens of the input, lexed up front
+    tokens: Vec<TokenData<'a>>,
+    /// Index of the current token in `tokens`, `tokens.len()` at EOF
     position: usize,
 }
 
 impl<'a> C1Lexer<'a> {
     /// Initialize a new C1Lexer for the given string slice
-    pub fn new(text: &'a str) -> C1Lexer {
-        let mut lexer = C1Lexer {
-            logos_lexer: C1Token::lexer(text),
-            logos_line_number: 1,
-            current_token: None,
-            past: vec![],
-            marks: 0,
+    pub fn new(text: &'a str) -> C1Lexer<'a> {
+        Self::new_with_aliases(text, &[])
+    }
+
+    /// Initialize a new C1Lexer that lexes identifiers matching one of the given lexemes as the
+    /// associated token instead. The token text is kept unchanged.
+    /// ```
+    /// use cb_3::{C1Lexer, C1Token};
+    /// let lexer = C1Lexer::new_with_aliases("mod", &[("mod", C1Token::Percent)]);
+    ///
+    /// assert_eq!(lexer.current_token(), Some(C1Token::Percent));
+    /// assert_eq!(lexer.current_text(), Some("mod"));
+    /// ```
+    pub fn new_with_aliases(text: &'a str, aliases: &[(&str, C1Token)]) -> C1Lexer<'a> {
+        let mut tokens = vec![];
+        lex_all(text, aliases, false, &mut tokens);
+        C1Lexer {
+            tokens,
             position: 0,
-        };
-        lexer.advance();
-        lexer
+        }
+    }
+
+    /// Initialize a new C1Lexer that keeps whitespace, line breaks and comments as tokens instead
+    /// of skipping them, for tools like editors that need every character of the input. The
+    /// parser cannot work with such a token stream.
+    pub fn new_with_trivia(text: &'a str) -> C1Lexer<'a> {
+        let mut tokens = vec![];
+        lex_all(text, &[], true, &mut tokens);
+        C1Lexer {
+            tokens,
+            position: 0,
+        }
+    }
+
+    /// Start over on a new input, reusing the token buffer. The lexer is in the same state as a
+    /// lexer created by `new`, aliases and float specials from other constructors are not kept.
+    pub fn reset(&mut self, text: &'a str) {
+        self.tokens.clear();
+        lex_all(text, &[], false, &mut self.tokens);
+        self.position = 0;
+    }
+
+    /// Initialize a new C1Lexer that additionally lexes `inf` and `nan` as ConstFloat. A negative
+    /// literal like `-0.0` is still a Minus followed by a ConstFloat, as in the default mode.
+    pub fn new_with_float_specials(text: &'a str) -> C1Lexer<'a> {
+        Self::new_with_aliases(
+            text,
+            &[("inf", C1Token::ConstFloat), ("nan", C1Token::ConstFloat)],
+        )
+    }
+
+    /// Cheaply check that every `else` follows an `if` at the same brace depth and that `case`
+    /// and `default` only occur inside a `switch` body. C1 has no switch statement, so these three
+    /// words are lexed as identifiers and recognized by their text.
+    pub fn validate_keyword_nesting(text: &str) -> Result<(), String> {
+        struct Scope {
+            open_ifs: usize,
+            in_switch: bool,
+        }
+
+        let mut lexer = C1Lexer::new(text);
+        let mut scopes = vec![Scope {
+            open_ifs: 0,
+            in_switch: false,
+        }];
+        let mut switch_pending = false;
+
+        while let (Some(token), Some(line)) = (lexer.current_token(), lexer.current_line_number()) {
+            let scope = scopes.last_mut().unwrap();
+            match (token, lexer.current_text().unwrap()) {
+                (C1Token::KwIf, _) => scope.open_ifs += 1,
+                (C1Token::KwElse, _) if scope.open_ifs == 0 => {
+                    return Err(format!("'else' without a preceding 'if' at line {}", line));
+                }
+                (C1Token::KwElse, _) => scope.open_ifs -= 1,
+                (C1Token::Identifier, "switch") => switch_pending = true,
+                (C1Token::Identifier, word @ ("case" | "default")) if !scope.in_switch => {
+                    return Err(format!("'{}' outside of a 'switch' at line {}", word, line));
+                }
+                (C1Token::LeftBrace, _) => {
+                    let in_switch = scope.in_switch || switch_pending;
+                    switch_pending = false;
+                    scopes.push(Scope {
+                        open_ifs: 0,
+                        in_switch,
+                    });
+                }
+                (C1Token::RightBrace, _) if scopes.len() == 1 => {
+                    return Err(format!("Unmatched '}}' at line {}", line));
+                }
+                (C1Token::RightBrace, _) => {
+                    scopes.pop();
+                }
+                _ => {}
+            }
+            lexer.advance();
+        }
+
+        Ok(())
+    }
+
+    /// Count the code, comment-only and blank lines of `text`. Lines are counted like
+    /// `str::lines` does, so a trailing line break does not start another line.
+    pub fn line_metrics(text: &str) -> LineMetrics {
+        // ordered so that a line takes the "most significant" kind of anything on it
+        #[derive(Clone, Copy, PartialEq, PartialOrd)]
+        enum Kind {
+            Blank,
+            Comment,
+            Code,
+        }
+
+        let mut lines = vec![Kind::Blank];
+        let mut lexer = C1Token::lexer(text);
+
+        while let Some(token) = lexer.next() {
+            let kind = match token {
+                C1Token::Linebreak => {
+                    lines.push(Kind::Blank);
+                    continue;
+                }
+                C1Token::Whitespace => continue,
+                C1Token::CComment | C1Token::CPPComment => Kind::Comment,
+                _ => Kind::Code,
+            };
+
+            let first = lines.len() - 1;
+            lines.extend(lexer.slice().matches('\n').map(|_| Kind::Blank));
+            for line in &mut lines[first..] {
+                if *line < kind {
+                    *line = kind;
+                }
+            }
+        }
+
+        if text.is_empty() || text.ends_with('\n') || text.ends_with('\r') {
+            lines.pop();
+        }
+
+        let count = |kind| lines.iter().filter(|line| **line == kind).count();
+        LineMetrics {
+            code_lines: count(Kind::Code),
+            comment_lines: count(Kind::Comment),
+            blank_lines: count(Kind::Blank),
+        }
+    }
+
+    /// Return the distinct arithmetic, bitwise, comparison, logical and assignment operators
+    /// used in `text`.
+    /// ```
+    /// use cb_3::{C1Lexer, C1Token};
+    /// let operators = C1Lexer::operators_used("x += 1;");
+    ///
+    /// assert!(operators.contains(&C1Token::PlusAssign));
+    /// assert_eq!(operators.len(), 1);
+    /// ```
+    pub fn operators_used(text: &str) -> HashSet<C1Token> {
+        C1Lexer::new(text)
+            .tokens()
+            .map(|token| token.token_type())
+            .filter(|token| {
+                matches!(
+                    token,
+                    C1Token::Plus
+                        | C1Token::Minus
+                        | C1Token::Increment
+                        | C1Token::Decrement
+                        | C1Token::Asterisk
+                        | C1Token::Slash
+                        | C1Token::Percent
+                        | C1Token::Assign
+                        | C1Token::PlusAssign
+                        | C1Token::MinusAssign
+                        | C1Token::StarAssign
+                        | C1Token::SlashAssign
+                        | C1Token::PercentAssign
+                        | C1Token::Equal
+                        | C1Token::NotEqual
+                        | C1Token::Not
+                        | C1Token::Less
+                        | C1Token::Greater
+                        | C1Token::LessEqual
+                        | C1Token::GreaterEqual
+                        | C1Token::ShiftLeft
+                        | C1Token::ShiftRight
+                        | C1Token::And
+                        | C1Token::Or
+                        | C1Token::BitAnd
+                        | C1Token::BitOr
+                        | C1Token::BitXor
+                )
+            })
+            .collect()
     }
 
     /// Return the C1Token variant of the current token without consuming it.
@@ -176,92 +619,383 @@ impl<'a> C1Lexer<'a> {
     /// assert_eq!(lexer.current_text(), Some("current"));
     /// ```
     pub fn current_token(&self) -> Option<C1Token> {
-        self.current_token.token_type()
+        self.current().token_type()
     }
 
     /// Return the text of the current token
     pub fn current_text(&self) -> Option<&str> {
-        self.current_token.text()
+        self.current().text()
     }
 
-
     /// Return the line number where the current token is located
     pub fn current_line_number(&self) -> Option<usize> {
-        self.current_token.line_number()
+        self.current().line_number()
     }
 
-    pub fn mark(&mut self) -> usize {
-        self.marks += 1;
-        if self.marks == 1 {
-            if let Some(t) = self.current_token {
-                self.past = vec!(t);
-                self.position = 1;
+    /// Return the 1-based column where the current token starts. Columns count characters, so a
+    /// tab advances the column by one just like any other character.
+    /// ```
+    /// use cb_3::C1Lexer;
+    /// let mut lexer = C1Lexer::new("int x;\n\tfoo");
+    ///
+    /// assert_eq!(lexer.current_column(), Some(1));
+    /// lexer.advance();
+    /// assert_eq!(lexer.current_column(), Some(5));
+    /// lexer.advance();
+    /// lexer.advance();
+    /// assert_eq!(lexer.current_column(), Some(2));
+    /// ```
+    pub fn current_column(&self) -> Option<usize> {
+        self.current().column()
+    }
+
+    /// Return the byte range of the current token in the original source
+    /// ```
+    /// use cb_3::C1Lexer;
+    /// let mut lexer = C1Lexer::new("current next");
+    ///
+    /// assert_eq!(lexer.current_span(), Some(0..7));
+    /// lexer.advance();
+    /// assert_eq!(lexer.current_span(), Some(8..12));
+    /// ```
+    pub fn current_span(&self) -> Option<Range<usize>> {
+        self.current().span()
+    }
+
+    /// Return the decoded value of the current token if it is a ConstString. The escapes `\n`,
+    /// `\t`, `\\` and `\"` are decoded; any other escape yields an error.
+    /// ```
+    /// use cb_3::C1Lexer;
+    /// let lexer = C1Lexer::new(r#""say \"hi\"\n""#);
+    ///
+    /// assert_eq!(lexer.current_string_value(), Some(Ok("say \"hi\"\n".to_string())));
+    /// ```
+    pub fn current_string_value(&self) -> Option<Result<String, String>> {
+        match (self.current_token(), self.current_text()) {
+            (Some(C1Token::ConstString), Some(text)) => {
+                Some(decode_escapes(&text[1..text.len() - 1]))
             }
-        } 
-        self.position-1
+            _ => None,
+        }
     }
 
+    /// Return the position of the current token, so that `undo` can return to it. Marks can be
+    /// nested and undone in any order, each one always restores exactly its own position.
+    /// ```
+    /// use cb_3::C1Lexer;
+    /// let mut lexer = C1Lexer::new("a b c");
+    /// let outer = lexer.mark();
+    /// lexer.advance();
+    /// let inner = lexer.mark();
+    /// lexer.advance();
+    ///
+    /// lexer.undo(outer);
+    /// assert_eq!(lexer.current_text(), Some("a"));
+    /// lexer.undo(inner);
+    /// assert_eq!(lexer.current_text(), Some("b"));
+    /// assert_eq!(lexer.previous_text(), Some("a"));
+    /// lexer.pop_mark();
+    /// lexer.pop_mark();
+    /// ```
+    pub fn mark(&mut self) -> usize {
+        self.position
+    }
+
+    /// Return to a position returned by `mark`
     pub fn undo(&mut self, marker: usize) {
+        debug_assert!(marker <= self.tokens.len(), "undo past EOF");
         self.position = marker;
-        self.advance();
     }
 
-    pub fn pop_mark(&mut self) {
-        self.marks -= 1;
+    /// Number of tokens advanced past, which is also the index of the current token and comparable
+    /// to the markers returned by `mark`. Undoing to a marker takes back the tokens consumed since,
+    /// so tokens that were only read speculatively are not counted.
+    pub fn tokens_consumed(&self) -> usize {
+        self.position
     }
 
-    pub fn advance(&mut self)  {
-        let next;
-        if self.position >= self.past.len() {
-            next = self.next_token_lexer();
-        } else {
-            next = Some(self.past[self.position]);
-            self.position += 1;
-        }
+    /// Release the most recent mark. All tokens are kept anyway, so this does nothing; it only
+    /// documents where a speculative parse ends.
+    pub fn pop_mark(&mut self) {}
 
-        if self.marks == 0 && self.position >= self.past.len() && self.past.len() != 0 {
-            self.past = vec![];
-            self.position = 0;
-        }
+    /// Consume the current token. At EOF nothing happens, so the cursor never moves past the
+    /// position of EOF and reading past the end cannot affect a later `undo`.
+    pub fn advance(&mut self) {
+        self.position = (self.position + 1).min(self.tokens.len());
+    }
 
-        self.current_token = next;
+    fn current(&self) -> Option<TokenData<'a>> {
+        self.tokens.get(self.position).copied()
     }
 
-    fn next_token_lexer(&mut self) -> Option<TokenData<'a>> {
-        if let Some(c1_token) = self.logos_lexer.next() {
-            match c1_token {
-                C1Token::Linebreak => {
-                    self.logos_line_number += 1;
-                    self.next_token_lexer()
+    fn previous(&self) -> Option<TokenData<'a>> {
+        self.position
+            .checked_sub(1)
+            .and_then(|position| self.tokens.get(position))
+            .copied()
+    }
+
+    /// Return the C1Token variant of the last consumed token, i.e. the one before the current one.
+    /// ```
+    /// use cb_3::{C1Lexer, C1Token};
+    /// let mut lexer = C1Lexer::new("return x");
+    /// assert_eq!(lexer.previous_token(), None);
+    ///
+    /// lexer.advance();
+    /// assert_eq!(lexer.previous_token(), Some(C1Token::KwReturn));
+    /// assert_eq!(lexer.previous_text(), Some("return"));
+    /// ```
+    pub fn previous_token(&self) -> Option<C1Token> {
+        self.previous().token_type()
+    }
+
+    /// Return the text of the last consumed token
+    pub fn previous_text(&self) -> Option<&str> {
+        self.previous().text()
+    }
+
+    /// Return the byte range of the last consumed token
+    pub fn previous_span(&self) -> Option<Range<usize>> {
+        self.previous().span()
+    }
+
+    /// Return the line number of the last consumed token
+    pub fn previous_line_number(&self) -> Option<usize> {
+        self.previous().line_number()
+    }
+
+    /// Return the column of the last consumed token
+    pub fn previous_column(&self) -> Option<usize> {
+        self.previous().column()
+    }
+
+    /// Return the line number and text of the last input that could not be lexed, if any, up to
+    /// and including the current token. An `Error` token counts as soon as it is the current one
+    /// and is kept after it was consumed.
+    /// ```
+    /// use cb_3::{C1Lexer, C1Token};
+    /// let lexer = C1Lexer::new("\n@");
+    ///
+    /// assert_eq!(lexer.current_token(), Some(C1Token::Error));
+    /// assert_eq!(lexer.last_error(), Some((2, "@".to_string())));
+    /// ```
+    pub fn last_error(&self) -> Option<(usize, String)> {
+        let seen = (self.position + 1).min(self.tokens.len());
+        self.tokens[..seen]
+            .iter()
+            .rev()
+            .find(|token| token.token_type == C1Token::Error)
+            .map(|token| (token.token_line, token.token_text.to_string()))
+    }
+
+    /// Consume the lexer and iterate over all remaining tokens, starting with the current one.
+    /// ```
+    /// use cb_3::{C1Lexer, C1Token};
+    /// let types: Vec<C1Token> = C1Lexer::new("int x;").tokens().map(|t| t.token_type()).collect();
+    ///
+    /// assert_eq!(types, vec![C1Token::KwInt, C1Token::Identifier, C1Token::Semicolon]);
+    /// ```
+    pub fn tokens(mut self) -> impl Iterator<Item = TokenData<'a>> {
+        std::iter::from_fn(move || {
+            let current = self.current();
+            self.advance();
+            current
+        })
+    }
+
+    /// Return the type of the token after the current one without consuming anything
+    /// ```
+    /// use cb_3::{C1Lexer, C1Token};
+    /// let mut lexer = C1Lexer::new("a b");
+    ///
+    /// assert_eq!(lexer.current_text(), Some("a"));
+    /// assert_eq!(lexer.peek_token(), Some(C1Token::Identifier));
+    /// assert_eq!(lexer.current_text(), Some("a"));
+    ///
+    /// lexer.advance();
+    /// assert_eq!(lexer.current_text(), Some("b"));
+    /// assert_eq!(lexer.peek_token(), None);
+    /// ```
+    pub fn peek_token(&mut self) -> Option<C1Token> {
+        self.peek_nth(1)
+    }
+
+    /// Return the type of the token `n` positions ahead without consuming anything, where `n == 0`
+    /// is the current token
+    /// ```
+    /// use cb_3::{C1Lexer, C1Token};
+    /// let mut lexer = C1Lexer::new("f ( )");
+    ///
+    /// assert_eq!(lexer.peek_nth(0), Some(C1Token::Identifier));
+    /// assert_eq!(lexer.peek_nth(2), Some(C1Token::RightParenthesis));
+    /// assert_eq!(lexer.peek_nth(3), None);
+    /// assert_eq!(lexer.current_text(), Some("f"));
+    /// ```
+    pub fn peek_nth(&mut self, n: usize) -> Option<C1Token> {
+        self.tokens
+            .get(self.position + n)
+            .map(|token| token.token_type)
+    }
+}
+
+/// Lex all of `text` into `tokens`, skipping whitespace, comments and line breaks unless `trivia`
+/// is set. Identifiers matching one of the `aliases` get the associated token type.
+fn lex_all<'a>(
+    text: &'a str,
+    aliases: &[(&str, C1Token)],
+    trivia: bool,
+    tokens: &mut Vec<TokenData<'a>>,
+) {
+    let mut logos_lexer = C1Token::lexer(text);
+    let mut line_number = 1;
+    // byte offset at which the line of `line_number` starts
+    let mut line_start = 0;
+
+    while let Some(c1_token) = logos_lexer.next() {
+        let text = logos_lexer.slice();
+        let span = logos_lexer.span();
+        let skipped = matches!(
+            c1_token,
+            C1Token::Whitespace | C1Token::Linebreak | C1Token::CComment | C1Token::CPPComment
+        );
+        if trivia || !skipped {
+            let line_prefix = &logos_lexer.source()[line_start..span.start];
+            let c1_token = match c1_token {
+                C1Token::Identifier => aliases
+                    .iter()
+                    .find(|(lexeme, _)| *lexeme == text)
+                    .map_or(c1_token, |(_, token)| *token),
+                _ => c1_token,
+            };
+            tokens.push(TokenData {
+                token_type: c1_token,
+                token_text: text,
+                token_line: line_number,
+                token_column: line_prefix.chars().count() + 1,
+                token_span: (span.start, span.end),
+            });
+        }
+
+        // a line break or multi-line comment is on the line it starts in, what follows it is not
+        match c1_token {
+            C1Token::Linebreak => {
+                line_number += 1;
+                line_start = span.end;
+            }
+            C1Token::CComment => {
+                if let Some(last_break) = text.rfind('\n') {
+                    line_number += text.matches('\n').count();
+                    line_start = span.start + last_break + 1;
                 }
-                _ => {
-                    let next= TokenData {
-                        token_type: c1_token,
-                        token_text: self.logos_lexer.slice(),
-                        token_line: self.logos_line_number
-                    };
-                    
-                    if self.marks > 0 {
-                        self.past.push(next);
-                    }
-                    self.position = self.past.len();
-                    Some(next)
-                },
             }
-        } else {
-            self.position = self.past.len() + 1;
-            None
+            _ => {}
+        }
+    }
+}
+
+/// Run the lexer over the whole text and collect (type, text, line) of every token.
+///
+/// Whitespace and comments are skipped just like in `C1Lexer`. Input that cannot be lexed is
+/// not dropped: it shows up as a `C1Token::Error` entry at its position, so callers that need a
+/// clean token stream have to check for it.
+pub fn tokenize(text: &str) -> Vec<(C1Token, &str, usize)> {
+    C1Lexer::new(text)
+        .tokens()
+        .map(|token| (token.token_type(), token.text(), token.line_number()))
+        .collect()
+}
+
+/// Like `tokenize`, but as a JSON array of objects like
+/// `{"kind":"<identifier>","text":"x","line":1,"column":5,"span":[4,5]}`. The kind is the
+/// `C1Token::name` of the token and the span holds its start and end byte offset.
+pub fn tokens_to_json(text: &str) -> String {
+    let objects: Vec<String> = C1Lexer::new(text)
+        .tokens()
+        .map(|token| {
+            format!(
+                r#"{{"kind":"{}","text":"{}","line":{},"column":{},"span":[{},{}]}}"#,
+                json::escape(token.token_type().name()),
+                json::escape(token.text()),
+                token.line_number(),
+                token.column(),
+                token.span().start,
+                token.span().end
+            )
+        })
+        .collect();
+    format!("[{}]", objects.join(","))
+}
+
+/// Line counts of a source text, see `C1Lexer::line_metrics`
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct LineMetrics {
+    /// Lines containing at least one token that is not a comment
+    pub code_lines: usize,
+    /// Lines containing only comments and whitespace
+    pub comment_lines: usize,
+    /// Lines containing only whitespace
+    pub blank_lines: usize,
+}
+
+/// Decode the escape sequences of a string literal's contents (without the quotes)
+fn decode_escapes(raw: &str) -> Result<String, String> {
+    let mut value = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => value.push('\n'),
+            Some('t') => value.push('\t'),
+            Some('\\') => value.push('\\'),
+            Some('"') => value.push('"'),
+            Some(other) => return Err(format!("Invalid escape sequence '\\{}'", other)),
+            None => return Err("Unterminated escape sequence".to_string()),
         }
     }
 
+    Ok(value)
 }
 
-/// Hidden struct for capsuling the data associated with a token.
+/// Struct for capsuling the data associated with a token.
 #[derive(Copy, Clone, Debug)]
 pub struct TokenData<'a> {
     token_type: C1Token,
     token_text: &'a str,
     token_line: usize,
+    token_column: usize,
+    /// Start and end byte offset, kept as a tuple so that TokenData stays Copy
+    token_span: (usize, usize),
+}
+
+impl<'a> TokenData<'a> {
+    /// Return the type of the token, aka. its C1Token variant.
+    pub fn token_type(&self) -> C1Token {
+        self.token_type
+    }
+
+    /// Return the text of the token
+    pub fn text(&self) -> &'a str {
+        self.token_text
+    }
+
+    /// Return the line number of the token
+    pub fn line_number(&self) -> usize {
+        self.token_line
+    }
+
+    /// Return the column number of the token
+    pub fn column(&self) -> usize {
+        self.token_column
+    }
+
+    /// Return the byte range of the token
+    pub fn span(&self) -> Range<usize> {
+        self.token_span.0..self.token_span.1
+    }
 }
 
 /// Hidden trait that makes it possible to implemented the required getter functionality directly for
@@ -270,9 +1004,13 @@ trait TokenDataProvider<'a> {
     /// Return the type of the token, aka. its C1Token variant.
     fn token_type(&self) -> Option<C1Token>;
     /// Return the text of the token
-    fn text(&self) -> Option<&str>;
+    fn text(&self) -> Option<&'a str>;
     /// Return the line number of the token
     fn line_number(&self) -> Option<usize>;
+    /// Return the column number of the token
+    fn column(&self) -> Option<usize>;
+    /// Return the byte range of the token
+    fn span(&self) -> Option<Range<usize>>;
 }
 
 impl<'a> TokenDataProvider<'a> for Option<TokenData<'a>> {
@@ -287,4 +1025,809 @@ impl<'a> TokenDataProvider<'a> for Option<TokenData<'a>> {
     fn line_number(&self) -> Option<usize> {
         self.as_ref().map(|data| data.token_line)
     }
-}
\ No newline at end of file
+
+    fn column(&self) -> Option<usize> {
+        self.as_ref().map(|data| data.token_column)
+    }
+
+    fn span(&self) -> Option<Range<usize>> {
+        self.as_ref()
+            .map(|data| data.token_span.0..data.token_span.1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::{tokenize, tokens_to_json, C1Lexer, C1Token, LineMetrics, TokenCategory};
+    use std::collections::HashSet;
+
+    fn lex(text: &str) -> Vec<(C1Token, String)> {
+        let mut lexer = C1Lexer::new(text);
+        let mut tokens = vec![];
+        while let (Some(token), Some(text)) = (lexer.current_token(), lexer.current_text()) {
+            tokens.push((token, text.to_string()));
+            lexer.advance();
+        }
+        tokens
+    }
+
+    fn expect(tokens: &[(C1Token, &str)]) -> Vec<(C1Token, String)> {
+        tokens.iter().map(|(t, s)| (*t, s.to_string())).collect()
+    }
+
+    #[test]
+    fn char_literals() {
+        assert_eq!(lex("'a'"), expect(&[(C1Token::ConstChar, "'a'")]));
+        assert_eq!(lex(r"'\n'"), expect(&[(C1Token::ConstChar, r"'\n'")]));
+        assert_eq!(lex(r"'\t'"), expect(&[(C1Token::ConstChar, r"'\t'")]));
+        assert_eq!(lex(r"'\\'"), expect(&[(C1Token::ConstChar, r"'\\'")]));
+        assert_eq!(lex(r"'\''"), expect(&[(C1Token::ConstChar, r"'\''")]));
+    }
+
+    #[test]
+    fn hex_literals() {
+        assert_eq!(lex("0x0"), expect(&[(C1Token::ConstInt, "0x0")]));
+        assert_eq!(lex("0XFF"), expect(&[(C1Token::ConstInt, "0XFF")]));
+        assert_eq!(
+            lex("0xdeadBEEF"),
+            expect(&[(C1Token::ConstInt, "0xdeadBEEF")])
+        );
+        assert_eq!(lex("0x"), expect(&[(C1Token::Error, "0x")]));
+    }
+
+    #[test]
+    fn binary_literals() {
+        assert_eq!(lex("0b0"), expect(&[(C1Token::ConstInt, "0b0")]));
+        assert_eq!(lex("0B1111"), expect(&[(C1Token::ConstInt, "0B1111")]));
+        assert_eq!(lex("0b2"), expect(&[(C1Token::Error, "0b2")]));
+        assert_eq!(lex("0b"), expect(&[(C1Token::Error, "0b")]));
+    }
+
+    #[test]
+    fn digit_separators() {
+        assert_eq!(lex("1_000"), expect(&[(C1Token::ConstInt, "1_000")]));
+        assert_eq!(
+            lex("1_000_000"),
+            expect(&[(C1Token::ConstInt, "1_000_000")])
+        );
+        assert_eq!(lex("3_000.5"), expect(&[(C1Token::ConstFloat, "3_000.5")]));
+        assert_eq!(lex(".5e1_0"), expect(&[(C1Token::ConstFloat, ".5e1_0")]));
+
+        assert_eq!(
+            lex("_1"),
+            expect(&[(C1Token::Error, "_"), (C1Token::ConstInt, "1")])
+        );
+        assert_eq!(
+            lex("1_"),
+            expect(&[(C1Token::ConstInt, "1"), (C1Token::Error, "_")])
+        );
+        assert_eq!(
+            lex("1__0"),
+            expect(&[
+                (C1Token::ConstInt, "1"),
+                (C1Token::Error, "_"),
+                (C1Token::Error, "_"),
+                (C1Token::ConstInt, "0"),
+            ])
+        );
+    }
+
+    #[test]
+    fn string_values() {
+        let lexer = C1Lexer::new(r#""a\tb\\c""#);
+        assert_eq!(
+            lexer.current_string_value(),
+            Some(Ok("a\tb\\c".to_string()))
+        );
+
+        let lexer = C1Lexer::new(r#""quote \" inside""#);
+        assert_eq!(lexer.current_token(), Some(C1Token::ConstString));
+        assert_eq!(
+            lexer.current_string_value(),
+            Some(Ok("quote \" inside".to_string()))
+        );
+
+        let lexer = C1Lexer::new(r#""bad \q""#);
+        assert!(matches!(lexer.current_string_value(), Some(Err(_))));
+
+        let lexer = C1Lexer::new("ident");
+        assert_eq!(lexer.current_string_value(), None);
+    }
+
+    #[test]
+    fn not_and_not_equal() {
+        assert_eq!(
+            lex("!a != b"),
+            expect(&[
+                (C1Token::Not, "!"),
+                (C1Token::Identifier, "a"),
+                (C1Token::NotEqual, "!="),
+                (C1Token::Identifier, "b"),
+            ])
+        );
+    }
+
+    #[test]
+    fn bitwise_and_logical_operators() {
+        assert_eq!(
+            lex("a && b & c || d | e ^ f"),
+            expect(&[
+                (C1Token::Identifier, "a"),
+                (C1Token::And, "&&"),
+                (C1Token::Identifier, "b"),
+                (C1Token::BitAnd, "&"),
+                (C1Token::Identifier, "c"),
+                (C1Token::Or, "||"),
+                (C1Token::Identifier, "d"),
+                (C1Token::BitOr, "|"),
+                (C1Token::Identifier, "e"),
+                (C1Token::BitXor, "^"),
+                (C1Token::Identifier, "f"),
+            ])
+        );
+    }
+
+    #[test]
+    fn shift_and_comparison_operators() {
+        assert_eq!(
+            lex("a << b >> c <= d < e"),
+            expect(&[
+                (C1Token::Identifier, "a"),
+                (C1Token::ShiftLeft, "<<"),
+                (C1Token::Identifier, "b"),
+                (C1Token::ShiftRight, ">>"),
+                (C1Token::Identifier, "c"),
+                (C1Token::LessEqual, "<="),
+                (C1Token::Identifier, "d"),
+                (C1Token::Less, "<"),
+                (C1Token::Identifier, "e"),
+            ])
+        );
+    }
+
+    #[test]
+    fn compound_assignment_operators() {
+        assert_eq!(
+            lex("a += b -= c *= d /= e %= f"),
+            expect(&[
+                (C1Token::Identifier, "a"),
+                (C1Token::PlusAssign, "+="),
+                (C1Token::Identifier, "b"),
+                (C1Token::MinusAssign, "-="),
+                (C1Token::Identifier, "c"),
+                (C1Token::StarAssign, "*="),
+                (C1Token::Identifier, "d"),
+                (C1Token::SlashAssign, "/="),
+                (C1Token::Identifier, "e"),
+                (C1Token::PercentAssign, "%="),
+                (C1Token::Identifier, "f"),
+            ])
+        );
+        assert_eq!(
+            lex("a + = b"),
+            expect(&[
+                (C1Token::Identifier, "a"),
+                (C1Token::Plus, "+"),
+                (C1Token::Assign, "="),
+                (C1Token::Identifier, "b"),
+            ])
+        );
+    }
+
+    #[test]
+    fn increment_and_decrement() {
+        assert_eq!(
+            lex("i++ --j"),
+            expect(&[
+                (C1Token::Identifier, "i"),
+                (C1Token::Increment, "++"),
+                (C1Token::Decrement, "--"),
+                (C1Token::Identifier, "j"),
+            ])
+        );
+        assert_eq!(
+            lex("a + +b"),
+            expect(&[
+                (C1Token::Identifier, "a"),
+                (C1Token::Plus, "+"),
+                (C1Token::Plus, "+"),
+                (C1Token::Identifier, "b"),
+            ])
+        );
+    }
+
+    #[test]
+    fn aliases() {
+        let mut lexer = C1Lexer::new_with_aliases("a mod b modulo", &[("mod", C1Token::Percent)]);
+        let mut tokens = vec![];
+        while let Some(token) = lexer.current_token() {
+            tokens.push(token);
+            lexer.advance();
+        }
+        assert_eq!(
+            tokens,
+            vec![
+                C1Token::Identifier,
+                C1Token::Percent,
+                C1Token::Identifier,
+                C1Token::Identifier
+            ]
+        );
+    }
+
+    #[test]
+    fn const_keyword() {
+        assert_eq!(
+            lex("const constant"),
+            expect(&[
+                (C1Token::KwConst, "const"),
+                (C1Token::Identifier, "constant")
+            ])
+        );
+    }
+
+    #[test]
+    fn spans() {
+        let mut lexer = C1Lexer::new("int x;\n  // comment\n  f()");
+        let mut spans = vec![];
+        while let Some(span) = lexer.current_span() {
+            spans.push(span);
+            lexer.advance();
+        }
+        assert_eq!(spans, vec![0..3, 4..5, 5..6, 22..23, 23..24, 24..25]);
+    }
+
+    #[test]
+    fn keyword_nesting() {
+        let valid = "if (a) { if (b) x(); else y(); } else { z(); }";
+        assert!(C1Lexer::validate_keyword_nesting(valid).is_ok());
+        assert!(C1Lexer::validate_keyword_nesting("switch (x) { case 1: { default: } }").is_ok());
+
+        let stray_else = "void f() {\n  x = 1;\n  else {}\n}";
+        let err = C1Lexer::validate_keyword_nesting(stray_else).unwrap_err();
+        assert!(err.contains("line 3"), "{}", err);
+        assert!(C1Lexer::validate_keyword_nesting("if (a) { } { else }").is_err());
+        assert!(C1Lexer::validate_keyword_nesting("if (a) x(); else y(); else z();").is_err());
+        assert!(C1Lexer::validate_keyword_nesting("void f() { case 1: }").is_err());
+    }
+
+    #[test]
+    fn columns() {
+        let mut lexer = C1Lexer::new("a bc\r\n  d\n\t\te  f\n\"ä\" g");
+        let mut positions = vec![];
+        while let (Some(line), Some(column)) = (lexer.current_line_number(), lexer.current_column())
+        {
+            positions.push((line, column));
+            lexer.advance();
+        }
+        assert_eq!(
+            positions,
+            vec![(1, 1), (1, 3), (2, 3), (3, 3), (3, 6), (4, 1), (4, 5)]
+        );
+    }
+
+    #[test]
+    fn line_numbers_after_line_comments() {
+        let mut lexer = C1Lexer::new("a // first\n// second\r\nb");
+        assert_eq!(lexer.current_line_number(), Some(1));
+        lexer.advance();
+        assert_eq!(lexer.current_text(), Some("b"));
+        assert_eq!(lexer.current_line_number(), Some(3));
+        assert_eq!(lexer.current_column(), Some(1));
+    }
+
+    #[test]
+    fn line_metrics() {
+        let text = "// header\n\
+                    int f() {\n\
+                    \t\n\
+                    /* two\n\
+                    lines */\n\
+                    return 1; // trailing\n\
+                    \n\
+                    }\n";
+        assert_eq!(
+            C1Lexer::line_metrics(text),
+            LineMetrics {
+                code_lines: 3,
+                comment_lines: 3,
+                blank_lines: 2
+            }
+        );
+        assert_eq!(C1Lexer::line_metrics(""), LineMetrics::default());
+        assert_eq!(C1Lexer::line_metrics("x").code_lines, 1);
+    }
+
+    #[test]
+    fn line_numbers_after_block_comments() {
+        let mut lexer = C1Lexer::new("a /* one\ntwo\nthree */ b\nc");
+        lexer.advance();
+        assert_eq!(lexer.current_text(), Some("b"));
+        assert_eq!(lexer.current_line_number(), Some(3));
+        assert_eq!(lexer.current_column(), Some(10));
+        lexer.advance();
+        assert_eq!(lexer.current_line_number(), Some(4));
+    }
+
+    #[test]
+    fn block_comments_with_operators() {
+        assert_eq!(lex("/* a * b */ x"), expect(&[(C1Token::Identifier, "x")]));
+        assert_eq!(lex("/* 3/4 */ x"), expect(&[(C1Token::Identifier, "x")]));
+        assert_eq!(lex("/** doc **/ x"), expect(&[(C1Token::Identifier, "x")]));
+        assert_eq!(lex("/**/ x"), expect(&[(C1Token::Identifier, "x")]));
+        assert_eq!(
+            lex("/* a */ x /* b */"),
+            expect(&[(C1Token::Identifier, "x")])
+        );
+        assert_eq!(
+            lex("/* open x"),
+            expect(&[
+                (C1Token::Error, "/*"),
+                (C1Token::Identifier, "open"),
+                (C1Token::Identifier, "x"),
+            ])
+        );
+    }
+
+    #[test]
+    fn float_specials() {
+        let mut lexer = C1Lexer::new_with_float_specials("inf nan -0.0 info");
+        let mut tokens = vec![];
+        while let (Some(token), Some(text)) = (lexer.current_token(), lexer.current_text()) {
+            tokens.push((token, text.to_string()));
+            lexer.advance();
+        }
+        assert_eq!(
+            tokens,
+            expect(&[
+                (C1Token::ConstFloat, "inf"),
+                (C1Token::ConstFloat, "nan"),
+                (C1Token::Minus, "-"),
+                (C1Token::ConstFloat, "0.0"),
+                (C1Token::Identifier, "info"),
+            ])
+        );
+        assert_eq!(lex("inf"), expect(&[(C1Token::Identifier, "inf")]));
+    }
+
+    #[test]
+    fn peek_across_mark_and_undo() {
+        let mut lexer = C1Lexer::new("a b c d");
+        assert_eq!(lexer.peek_token(), Some(C1Token::Identifier));
+
+        let m = lexer.mark();
+        lexer.advance();
+        assert_eq!(lexer.current_text(), Some("b"));
+        lexer.peek_token();
+        lexer.advance();
+        assert_eq!(lexer.current_text(), Some("c"));
+
+        lexer.undo(m);
+        lexer.pop_mark();
+        assert_eq!(lexer.current_text(), Some("a"));
+        assert_eq!(lexer.peek_token(), Some(C1Token::Identifier));
+        lexer.advance();
+        lexer.advance();
+        lexer.advance();
+        assert_eq!(lexer.current_text(), Some("d"));
+        assert_eq!(lexer.peek_token(), None);
+        lexer.advance();
+        assert_eq!(lexer.current_token(), None);
+    }
+
+    #[test]
+    fn peek_nth_keeps_cursor_and_marks() {
+        let mut lexer = C1Lexer::new("a = b ( ) ;");
+        assert_eq!(lexer.peek_nth(5), Some(C1Token::Semicolon));
+        assert_eq!(lexer.peek_nth(1), Some(C1Token::Assign));
+        assert_eq!(lexer.peek_nth(6), None);
+        assert_eq!(lexer.peek_nth(7), None);
+        assert_eq!(lexer.current_text(), Some("a"));
+
+        let m = lexer.mark();
+        lexer.advance();
+        lexer.advance();
+        assert_eq!(lexer.current_text(), Some("b"));
+        assert_eq!(lexer.peek_nth(1), Some(C1Token::LeftParenthesis));
+        lexer.advance();
+        lexer.advance();
+        lexer.undo(m);
+        lexer.pop_mark();
+
+        // the tokens between the mark and the farthest read now come from the buffer
+        assert_eq!(lexer.current_text(), Some("a"));
+        assert_eq!(lexer.peek_nth(3), Some(C1Token::LeftParenthesis));
+        assert_eq!(lexer.peek_nth(5), Some(C1Token::Semicolon));
+
+        let mut texts = vec![];
+        while let Some(text) = lexer.current_text() {
+            texts.push(text.to_string());
+            lexer.advance();
+        }
+        assert_eq!(texts, vec!["a", "=", "b", "(", ")", ";"]);
+    }
+
+    #[test]
+    fn nested_marks() {
+        let mut lexer = C1Lexer::new("a b c d e f");
+        lexer.advance();
+        let outer = lexer.mark();
+        lexer.advance();
+        let middle = lexer.mark();
+        lexer.advance();
+        let inner = lexer.mark();
+        lexer.advance();
+        lexer.advance();
+        assert_eq!(lexer.current_text(), Some("f"));
+
+        // undoing an outer mark does not invalidate the inner ones
+        lexer.undo(middle);
+        assert_eq!(
+            (lexer.current_text(), lexer.previous_text()),
+            (Some("c"), Some("b"))
+        );
+        lexer.undo(inner);
+        assert_eq!(
+            (lexer.current_text(), lexer.previous_text()),
+            (Some("d"), Some("c"))
+        );
+        lexer.undo(outer);
+        assert_eq!(
+            (lexer.current_text(), lexer.previous_text()),
+            (Some("b"), Some("a"))
+        );
+
+        // releasing the inner marks keeps the tokens of the outer one
+        lexer.pop_mark();
+        lexer.advance();
+        lexer.advance();
+        lexer.advance();
+        lexer.pop_mark();
+        let again = lexer.mark();
+        assert_eq!(again, inner + 1);
+        lexer.advance();
+        lexer.undo(outer);
+        assert_eq!(lexer.current_text(), Some("b"));
+        lexer.undo(again);
+        assert_eq!(
+            (lexer.current_text(), lexer.previous_text()),
+            (Some("e"), Some("d"))
+        );
+        lexer.pop_mark();
+        lexer.pop_mark();
+
+        lexer.advance();
+        lexer.advance();
+        assert_eq!(lexer.current_token(), None);
+        assert_eq!(lexer.previous_text(), Some("f"));
+    }
+
+    #[test]
+    fn positions_are_indices() {
+        let mut lexer = C1Lexer::new("a /* b */ c\n// d\ne");
+        assert_eq!(lexer.mark(), 0);
+        lexer.advance();
+        assert_eq!(lexer.mark(), 1);
+        lexer.pop_mark();
+        lexer.pop_mark();
+        lexer.advance();
+        lexer.advance();
+        assert_eq!(lexer.mark(), 3);
+        lexer.pop_mark();
+
+        // popped marks stay valid, and peeking does not depend on the cursor history
+        lexer.undo(1);
+        assert_eq!(
+            (lexer.current_text(), lexer.previous_text()),
+            (Some("c"), Some("a"))
+        );
+        assert_eq!(lexer.peek_nth(1), Some(C1Token::Identifier));
+        assert_eq!(lexer.peek_nth(2), None);
+        lexer.undo(0);
+        assert_eq!(
+            (lexer.current_text(), lexer.previous_text()),
+            (Some("a"), None)
+        );
+        lexer.undo(2);
+        assert_eq!(lexer.current_line_number(), Some(3));
+    }
+
+    #[test]
+    fn undo_from_eof() {
+        let mut lexer = C1Lexer::new("a b");
+        lexer.advance();
+        let m = lexer.mark();
+        lexer.advance();
+        assert_eq!(lexer.current_token(), None);
+        lexer.advance();
+        lexer.advance();
+        assert_eq!(lexer.current_token(), None);
+        assert_eq!(lexer.peek_token(), None);
+
+        lexer.undo(m);
+        assert_eq!(lexer.current_text(), Some("b"));
+        assert_eq!(lexer.previous_text(), Some("a"));
+        lexer.pop_mark();
+        lexer.advance();
+        assert_eq!(lexer.current_token(), None);
+        assert_eq!(lexer.previous_text(), Some("b"));
+
+        // a mark taken at EOF returns to EOF
+        let m = lexer.mark();
+        lexer.advance();
+        lexer.undo(m);
+        lexer.pop_mark();
+        assert_eq!(
+            (lexer.current_token(), lexer.previous_text()),
+            (None, Some("b"))
+        );
+    }
+
+    #[test]
+    fn display() {
+        let names: Vec<String> = [
+            C1Token::Plus,
+            C1Token::PercentAssign,
+            C1Token::Or,
+            C1Token::LeftBrace,
+            C1Token::KwIf,
+            C1Token::KwBoolean,
+            C1Token::Identifier,
+            C1Token::ConstInt,
+            C1Token::ConstString,
+            C1Token::Error,
+        ]
+        .iter()
+        .map(C1Token::to_string)
+        .collect();
+        assert_eq!(
+            names,
+            [
+                "+",
+                "%=",
+                "||",
+                "{",
+                "if",
+                "bool",
+                "<identifier>",
+                "<integer>",
+                "<string>",
+                "<invalid token>"
+            ]
+        );
+    }
+
+    #[test]
+    fn last_error() {
+        let mut lexer = C1Lexer::new("a @ b");
+        assert_eq!(lexer.last_error(), None);
+        lexer.advance();
+        assert_eq!(lexer.last_error(), Some((1, "@".to_string())));
+        lexer.advance();
+        assert_eq!(lexer.last_error(), Some((1, "@".to_string())));
+    }
+
+    #[test]
+    fn previous_token() {
+        let mut lexer = C1Lexer::new("a b c d");
+        lexer.advance();
+        assert_eq!(lexer.previous_text(), Some("a"));
+
+        let m = lexer.mark();
+        lexer.advance();
+        lexer.advance();
+        assert_eq!(lexer.previous_text(), Some("c"));
+        lexer.undo(m);
+        assert_eq!(lexer.current_text(), Some("b"));
+        assert_eq!(lexer.previous_text(), Some("a"));
+
+        lexer.advance();
+        let inner = lexer.mark();
+        lexer.advance();
+        lexer.advance();
+        assert_eq!(lexer.current_token(), None);
+        assert_eq!(lexer.previous_text(), Some("d"));
+        lexer.undo(inner);
+        assert_eq!(lexer.current_text(), Some("c"));
+        assert_eq!(lexer.previous_text(), Some("b"));
+        lexer.pop_mark();
+        lexer.pop_mark();
+
+        lexer.advance();
+        assert_eq!(lexer.previous_token(), Some(C1Token::Identifier));
+        assert_eq!(lexer.previous_text(), Some("c"));
+    }
+
+    #[test]
+    fn operators_used() {
+        let expected: HashSet<C1Token> = [C1Token::Plus, C1Token::Asterisk, C1Token::And].into();
+        assert_eq!(C1Lexer::operators_used("a + b * c && d"), expected);
+
+        let expected: HashSet<C1Token> = [C1Token::Assign, C1Token::Less].into();
+        assert_eq!(C1Lexer::operators_used("x = y < z; x = (1 < 2);"), expected);
+        assert!(C1Lexer::operators_used("void f() { g(); }").is_empty());
+    }
+
+    #[test]
+    fn loop_control_keywords() {
+        assert_eq!(
+            lex("break continue breaks"),
+            expect(&[
+                (C1Token::KwBreak, "break"),
+                (C1Token::KwContinue, "continue"),
+                (C1Token::Identifier, "breaks"),
+            ])
+        );
+    }
+
+    #[test]
+    fn ternary_tokens() {
+        assert_eq!(
+            lex("a?b:c"),
+            expect(&[
+                (C1Token::Identifier, "a"),
+                (C1Token::Question, "?"),
+                (C1Token::Identifier, "b"),
+                (C1Token::Colon, ":"),
+                (C1Token::Identifier, "c"),
+            ])
+        );
+    }
+
+    #[test]
+    fn brackets() {
+        assert_eq!(
+            lex("a[1]"),
+            expect(&[
+                (C1Token::Identifier, "a"),
+                (C1Token::LeftBracket, "["),
+                (C1Token::ConstInt, "1"),
+                (C1Token::RightBracket, "]"),
+            ])
+        );
+    }
+
+    #[test]
+    fn gcc_attribute_keyword() {
+        assert_eq!(
+            lex("__attribute__((cold))"),
+            expect(&[
+                (C1Token::KwAttribute, "__attribute__"),
+                (C1Token::LeftParenthesis, "("),
+                (C1Token::LeftParenthesis, "("),
+                (C1Token::Identifier, "cold"),
+                (C1Token::RightParenthesis, ")"),
+                (C1Token::RightParenthesis, ")"),
+            ])
+        );
+    }
+
+    #[test]
+    fn token_iterator() {
+        let tokens: Vec<_> = C1Lexer::new("int\n x").tokens().collect();
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[0].token_type(), C1Token::KwInt);
+        assert_eq!(tokens[0].line_number(), 1);
+        assert_eq!(tokens[1].token_type(), C1Token::Identifier);
+        assert_eq!(tokens[1].text(), "x");
+        assert_eq!(tokens[1].line_number(), 2);
+        assert_eq!(tokens[1].column(), 2);
+        assert_eq!(tokens[1].span(), 5..6);
+
+        let mut lexer = C1Lexer::new("a b c");
+        lexer.advance();
+        assert_eq!(lexer.tokens().count(), 2);
+    }
+
+    #[test]
+    fn tokenize_function() {
+        assert_eq!(
+            tokenize("int f() {}"),
+            vec![
+                (C1Token::KwInt, "int", 1),
+                (C1Token::Identifier, "f", 1),
+                (C1Token::LeftParenthesis, "(", 1),
+                (C1Token::RightParenthesis, ")", 1),
+                (C1Token::LeftBrace, "{", 1),
+                (C1Token::RightBrace, "}", 1),
+            ]
+        );
+        assert_eq!(
+            tokenize("a\n$ // comment"),
+            vec![(C1Token::Identifier, "a", 1), (C1Token::Error, "$", 2)]
+        );
+    }
+
+    #[test]
+    fn json_tokens() {
+        let json = tokens_to_json("int\n  \"a\\\"b\"");
+        assert_eq!(
+            json,
+            r#"[{"kind":"int","text":"int","line":1,"column":1,"span":[0,3]},"#.to_string()
+                + r#"{"kind":"<string>","text":"\"a\\\"b\"","line":2,"column":3,"span":[6,12]}]"#
+        );
+        assert_eq!(tokens_to_json(""), "[]");
+
+        #[cfg(feature = "serde")]
+        {
+            let tokens: serde_json::Value = serde_json::from_str(&json).unwrap();
+            assert_eq!(tokens[1]["kind"], "<string>");
+            assert_eq!(tokens[1]["text"], r#""a\"b""#);
+            assert_eq!(tokens[1]["line"], 2);
+            assert_eq!(tokens[1]["column"], 3);
+            assert_eq!(tokens[1]["span"], serde_json::json!([6, 12]));
+        }
+    }
+
+    #[test]
+    fn categories() {
+        let categories: Vec<_> = C1Lexer::new_with_trivia("if (x <= 1.5) return 'c'; // done\n$")
+            .tokens()
+            .map(|token| (token.token_type().category(), token.text()))
+            .filter(|(category, _)| *category != TokenCategory::Whitespace)
+            .collect();
+        assert_eq!(
+            categories,
+            vec![
+                (TokenCategory::Keyword, "if"),
+                (TokenCategory::Punctuation, "("),
+                (TokenCategory::Identifier, "x"),
+                (TokenCategory::Operator, "<="),
+                (TokenCategory::FloatLiteral, "1.5"),
+                (TokenCategory::Punctuation, ")"),
+                (TokenCategory::Keyword, "return"),
+                (TokenCategory::StringLiteral, "'c'"),
+                (TokenCategory::Punctuation, ";"),
+                (TokenCategory::Comment, "// done"),
+                (TokenCategory::Error, "$"),
+            ]
+        );
+        assert_eq!(C1Token::ConstInt.category(), TokenCategory::IntLiteral);
+        assert_eq!(C1Token::ConstBoolean.category(), TokenCategory::BoolLiteral);
+        assert_eq!(
+            C1Token::ConstString.category(),
+            TokenCategory::StringLiteral
+        );
+        assert_eq!(C1Token::Question.category(), TokenCategory::Operator);
+        assert_eq!(C1Token::CComment.category(), TokenCategory::Comment);
+    }
+
+    #[test]
+    fn trivia_tokens() {
+        let text = "a /* b\n */\tc\n";
+        let tokens: Vec<_> = C1Lexer::new_with_trivia(text)
+            .tokens()
+            .map(|token| (token.token_type(), token.line_number(), token.column()))
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (C1Token::Identifier, 1, 1),
+                (C1Token::Whitespace, 1, 2),
+                (C1Token::CComment, 1, 3),
+                (C1Token::Whitespace, 2, 4),
+                (C1Token::Identifier, 2, 5),
+                (C1Token::Linebreak, 2, 6),
+            ]
+        );
+        // together the tokens cover the whole input
+        let rebuilt: String = C1Lexer::new_with_trivia(text)
+            .tokens()
+            .map(|t| t.text())
+            .collect();
+        assert_eq!(rebuilt, text);
+    }
+
+    #[test]
+    fn unterminated_char_literal() {
+        assert_eq!(
+            lex("'a b;"),
+            expect(&[
+                (C1Token::Error, "'"),
+                (C1Token::Identifier, "a"),
+                (C1Token::Identifier, "b"),
+                (C1Token::Semicolon, ";"),
+            ])
+        );
+    }
+}
@@ -1,11 +1,35 @@
+pub mod analysis;
+pub mod ast;
+mod cst;
+mod error;
+pub mod eval;
+pub mod fold;
+mod format;
+mod highlight;
+pub mod interpreter;
+mod json;
 mod lexer;
 mod parser;
+pub mod symbols;
+pub mod typecheck;
+pub mod visitor;
 
 // Type definition for the Result that is being used by the parser. You may change it to anything
 // you want
-pub type ParseResult = Result<(), String>;
+pub type ParseResult = Result<(), ParseError>;
 
+pub use cst::CstKind;
+pub use cst::CstNode;
+pub use error::render_diagnostic;
+pub use error::ParseError;
+pub use format::parse_printf_format;
+pub use format::FormatSpec;
+pub use highlight::highlight_html;
+pub use lexer::tokenize;
+pub use lexer::tokens_to_json;
 pub use lexer::C1Lexer;
 pub use lexer::C1Token;
+pub use lexer::LineMetrics;
+pub use lexer::TokenCategory;
+pub use lexer::TokenData;
 pub use parser::C1Parser;
-
@@ -0,0 +1,107 @@
+use cb_3::{render_diagnostic, tokenize, C1Parser, ParseError};
+use std::env;
+use std::fs;
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: cb-3 [--all] [--tokens] [--json] <file>
+
+Parse a C1 program and print \"OK\", or the first syntax error.
+
+  --all     report every syntax error instead of only the first one
+  --tokens  print the token stream instead of parsing
+  --json    print the abstract syntax tree as JSON, needs the `serde` feature";
+
+/// Output requested on the command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Check,
+    Tokens,
+    Json,
+}
+
+fn main() -> ExitCode {
+    let mut mode = Mode::Check;
+    let mut all = false;
+    let mut path = None;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--all" => all = true,
+            "--tokens" => mode = Mode::Tokens,
+            "--json" => mode = Mode::Json,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            }
+            _ if arg.starts_with('-') || path.is_some() => return usage_error(),
+            _ => path = Some(arg),
+        }
+    }
+    let Some(path) = path else {
+        return usage_error();
+    };
+
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(err) => {
+            eprintln!("error: {}: {}", path, err);
+            return ExitCode::from(2);
+        }
+    };
+
+    let result = match mode {
+        Mode::Tokens => {
+            for (token, text, line) in tokenize(&text) {
+                println!("{}\t{:?}\t{}", line, token, text);
+            }
+            return ExitCode::SUCCESS;
+        }
+        Mode::Json => return print_json(&path, &text),
+        Mode::Check if all => C1Parser::parse_all(&text),
+        Mode::Check => C1Parser::parse(&text).map_err(|err| vec![err]),
+    };
+    match result {
+        Ok(()) => {
+            println!("OK");
+            ExitCode::SUCCESS
+        }
+        Err(errors) => {
+            errors.iter().for_each(|err| report(&path, &text, err));
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn usage_error() -> ExitCode {
+    eprintln!("{}", USAGE);
+    ExitCode::from(2)
+}
+
+/// Print `err` to stderr, headed by the location in the file at `path`
+fn report(path: &str, text: &str, err: &ParseError) {
+    let location = match err.column {
+        Some(column) => format!("{}:{}:{}", path, err.line, column),
+        None => format!("{}:{}", path, err.line),
+    };
+    eprintln!("{}: error: {}", location, err);
+    eprintln!("{}", render_diagnostic(text, err));
+}
+
+#[cfg(feature = "serde")]
+fn print_json(path: &str, text: &str) -> ExitCode {
+    match C1Parser::parse_to_ast(text) {
+        Ok(program) => {
+            println!("{}", cb_3::ast::to_json(&program));
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            report(path, text, &err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(not(feature = "serde"))]
+fn print_json(_path: &str, _text: &str) -> ExitCode {
+    eprintln!("error: --json needs cb-3 to be built with the `serde` feature");
+    ExitCode::from(2)
+}
@@ -1,277 +1,596 @@
+use crate::analysis;
+use crate::ast::{
+    AssignOp, BinaryOp, Declaration, Expr, ExprKind, Function, IncDecOp, Item, Parameter, Program,
+    Signature, Span, Stmt, StmtKind, Type, UnaryOp, Variable,
+};
+use crate::cst::{self, CstKind, CstNode};
+use crate::error::ParseError;
+use crate::json;
 use crate::lexer::{C1Lexer, C1Token};
 use crate::ParseResult;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
 use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+/// Operators that may follow the target of a statassignment
+const ASSIGNMENT_OPERATORS: [C1Token; 6] = [
+    C1Token::Assign,
+    C1Token::PlusAssign,
+    C1Token::MinusAssign,
+    C1Token::StarAssign,
+    C1Token::SlashAssign,
+    C1Token::PercentAssign,
+];
+
+/// Keywords that can only start a statement, `synchronize` resumes parsing in front of them
+const STATEMENT_KEYWORDS: [C1Token; 13] = [
+    C1Token::KwAsm,
+    C1Token::KwBoolean,
+    C1Token::KwBreak,
+    C1Token::KwContinue,
+    C1Token::KwDo,
+    C1Token::KwFloat,
+    C1Token::KwFor,
+    C1Token::KwIf,
+    C1Token::KwInt,
+    C1Token::KwPrintf,
+    C1Token::KwReturn,
+    C1Token::KwVoid,
+    C1Token::KwWhile,
+];
+
+// Levels of the binary operators for `binary_expr`, from the loosest to the tightest binding
+const LOGOR: u8 = 0;
+const LOGAND: u8 = 1;
+const BITAND: u8 = 4;
+const COMPARISON: u8 = 5;
+const SIMPEXPR: u8 = 7;
+const TERM: u8 = 8;
+
+/// How deeply statements and expressions may be nested by default, see `parse_with_max_depth`
+const DEFAULT_MAX_DEPTH: usize = 256;
+
+/// Stack of the thread `program` runs on. A nesting level takes up to about 10 KB of it in a
+/// debug build, a call argument being the most expensive one, so the default depth fits with
+/// plenty of room.
+const STACK_SIZE: usize = 16 << 20;
+
+/// The init, condition and update of a for loop
+type ForHeader = (Option<Box<Stmt>>, Expr, Option<Box<Stmt>>);
+
+pub struct C1Parser<'a> {
+    lexer: C1Lexer<'a>,
+    /// Accept inline assembly statements, see `parse_with_asm`
+    allow_asm: bool,
+    /// Skip `__attribute__((...))` before function definitions, see `parse_with_gcc_attributes`
+    allow_gcc_attributes: bool,
+    /// `(from, to)` cursor positions of every undo that moves back, see `parse_with_backtrack_log`
+    backtrack_log: Option<Vec<(usize, usize)>>,
+    /// Errors recovered from so far, `None` if parsing stops at the first error, see `parse_all`
+    errors: Option<Vec<ParseError>>,
+    /// Result and end position of factors parsed so far, by start position, see `memoized_factor`
+    memo: HashMap<usize, (Result<Expr, ParseError>, usize)>,
+    /// Number of nested rules being parsed, see `nested`
+    depth: usize,
+    /// Depth at which parsing fails instead of recursing further
+    max_depth: usize,
+    /// Set once `max_depth` is hit, so that the error is passed up unchanged instead of being
+    /// backtracked over or extended with the context of every enclosing rule
+    depth_exceeded: bool,
+    /// Number of errors built for the current input, including the ones given up when
+    /// backtracking
+    errors_built: Cell<usize>,
+}
 
-pub struct C1Parser<'a>(C1Lexer<'a>);
 // Implement Deref and DerefMut to enable the direct use of the lexer's methods
 impl<'a> Deref for C1Parser<'a> {
     type Target = C1Lexer<'a>;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.lexer
     }
 }
 
 impl<'a> DerefMut for C1Parser<'a> {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        &mut self.lexer
     }
 }
 
 impl<'a> C1Parser<'a> {
     pub fn parse(text: &str) -> ParseResult {
+        Self::parse_to_ast(text).map(|_| ())
+    }
+
+    /// Parse `text` and return its abstract syntax tree
+    pub fn parse_to_ast(text: &str) -> Result<Program, ParseError> {
         let mut parser = Self::initialize_parser(text);
         parser.program()
     }
 
-    fn initialize_parser(text: &str) -> C1Parser {
-        C1Parser(C1Lexer::new(text))
+    /// Like `parse`, but additionally accepts inline assembly statements `asm("...");`
+    pub fn parse_with_asm(text: &str) -> ParseResult {
+        let mut parser = Self::initialize_parser(text);
+        parser.allow_asm = true;
+        parser.program().map(|_| ())
     }
 
-    /// program ::= ( functiondefinition )* <EOF>
-    pub fn program(&mut self) -> ParseResult {
-        while let Some(_) = self.current_token() {
-            self.function_definition()?;
-        }
-
-        Ok(())
+    /// Like `parse`, but skips GCC-style `__attribute__((...))` attributes in front of function
+    /// definitions, so that GCC-flavoured headers can be parsed
+    pub fn parse_with_gcc_attributes(text: &str) -> ParseResult {
+        let mut parser = Self::initialize_parser(text);
+        parser.allow_gcc_attributes = true;
+        parser.program().map(|_| ())
     }
 
-    fn function_definition(&mut self) -> ParseResult {
-        self.p_type()
-            .and_then(|_| self.check_and_eat_token(&C1Token::Identifier, "Expected function name"))
-            .and_then(|_| self.check_and_eat_token(&C1Token::LeftParenthesis, r#"Expected "(""#))
-            .and_then(|_| self.check_and_eat_token(&C1Token::RightParenthesis, r#"Expected ")""#))
-            .and_then(|_| self.check_and_eat_token(&C1Token::LeftBrace, r#"Expected "{""#))
-            .and_then(|_| self.statementlist())
-            .and_then(|_| self.check_and_eat_token(&C1Token::RightBrace, r#"Expected "}""#))
-            .map_err(|err| err + ", in function definition")
+    /// Like `parse`, but additionally return every backtrack as a `(from_position, to_position)`
+    /// pair of token positions, in the order they happened. Only undos that give back tokens
+    /// which were consumed speculatively are logged, so `from > to` holds for every pair.
+    pub fn parse_with_backtrack_log(text: &str) -> (ParseResult, Vec<(usize, usize)>) {
+        let mut parser = Self::initialize_parser(text);
+        parser.backtrack_log = Some(vec![]);
+        let result = parser.program().map(|_| ());
+        (result, parser.backtrack_log.unwrap_or_default())
     }
 
-    fn functioncall(&mut self) -> ParseResult {
-        self.check_and_eat_token(&C1Token::Identifier, "Expected <ID>")
-            .and_then(|_| self.check_and_eat_token(&C1Token::LeftParenthesis, r#"Expected "(""#))
-            .and_then(|_| self.check_and_eat_token(&C1Token::RightParenthesis, r#"Expected ")""#))
-            .map_err(|err| err + ", in functioncall")
+    /// Like `parse`, but fail with "maximum nesting depth exceeded" once statements or
+    /// expressions are nested `max_depth` levels deep, instead of the default of 256
+    pub fn parse_with_max_depth(text: &str, max_depth: usize) -> ParseResult {
+        let mut parser = Self::initialize_parser(text);
+        parser.max_depth = max_depth;
+        parser.program().map(|_| ())
     }
 
-    fn statementlist(&mut self) -> ParseResult {
-        let mut m = self.mark();
-
-        while let Ok(_) = self.block() {
-            self.pop_mark();
-            m = self.mark();
+    /// Like `parse`, but instead of stopping at the first error, skip the offending statement (or
+    /// top-level item) and continue, so that every error is reported in the order it was found
+    pub fn parse_all(text: &str) -> Result<(), Vec<ParseError>> {
+        match Self::parse_recovering(text).1 {
+            errors if errors.is_empty() => Ok(()),
+            errors => Err(errors),
         }
+    }
 
-        self.undo(m);
-        self.pop_mark();
+    /// Return the functions of `text` together with every error, like `parse_all`. A function
+    /// with a broken statement is kept without that statement, one whose head or braces are
+    /// broken is left out.
+    pub fn parse_best_effort(text: &str) -> (Vec<Function>, Vec<ParseError>) {
+        let (items, errors) = Self::parse_recovering(text);
+        let functions = items
+            .into_iter()
+            .filter_map(|item| match item {
+                Item::Function(function) => Some(function),
+                Item::Global(_) => None,
+            })
+            .collect();
+        (functions, errors)
+    }
 
-        Ok(())
+    /// Parse `text`, skipping what cannot be parsed, and return the items that could be parsed
+    /// together with the errors
+    fn parse_recovering(text: &str) -> (Vec<Item>, Vec<ParseError>) {
+        let mut parser = Self::initialize_parser(text);
+        parser.errors = Some(vec![]);
+        // the program never fails while recovering
+        let items = parser.program().map_or(vec![], |program| program.items);
+        (items, parser.errors.unwrap_or_default())
     }
 
-    fn block(&mut self) -> ParseResult {
-        let m = self.mark();
-        let res = self
-            .check_and_eat_token(&C1Token::LeftBrace, r#"Expected "{""#)
-            .and_then(|_| self.statementlist())
-            .and_then(|_| self.check_and_eat_token(&C1Token::RightBrace, r#"Expected "}""#))
-            .or_else(|_| {
-                self.undo(m);
-                self.statement()
-            });
-        self.pop_mark();
-        res
+    /// Like `parse_all`, but return at most the first `max_errors` errors, and whether there were
+    /// more that are left out
+    pub fn parse_errors_capped(text: &str, max_errors: usize) -> (Vec<ParseError>, bool) {
+        let mut errors = Self::parse_all(text).err().unwrap_or_default();
+        let truncated = errors.len() > max_errors;
+        errors.truncate(max_errors);
+        (errors, truncated)
     }
 
-    fn statement(&mut self) -> ParseResult {
-        let m = self.mark();
-        let res = self
-            .ifstatement()
-            .or_else(|_| {
-                self.undo(m);
-                self.returnstatement()?;
-                self.check_and_eat_token(
-                    &C1Token::Semicolon,
-                    "Expected semicolon after return statement",
-                )
-            })
-            .or_else(|_| {
-                self.undo(m);
-                self.printf()?;
-                self.check_and_eat_token(&C1Token::Semicolon, "Expected semicolon after printf")
-            })
-            .or_else(|_| {
-                self.undo(m);
-                self.statassignment()?;
-                self.check_and_eat_token(
-                    &C1Token::Semicolon,
-                    "Expected semicolon after stat assignment",
-                )
+    /// Parse `text` into a lossless concrete syntax tree, so that `CstNode::text` gives back the
+    /// exact input. Function definitions, global declarations and blocks are inner nodes, all
+    /// tokens including whitespace and comments are leaves.
+    pub fn parse_cst(text: &str) -> Result<CstNode, ParseError> {
+        let program = Self::parse_to_ast(text)?;
+        let items: Vec<(CstKind, Span)> = program
+            .items
+            .iter()
+            .map(|item| match item {
+                Item::Function(function) => (CstKind::FunctionDefinition, function.span),
+                Item::Global(declaration) => (CstKind::Declaration, declaration.span),
             })
-            .or_else(|_| {
-                self.undo(m);
-                self.functioncall()?;
-                self.check_and_eat_token(
-                    &C1Token::Semicolon,
-                    "Expected semicolon after function call",
-                )
-            });
+            .collect();
+        Ok(cst::build(text, &items))
+    }
 
-        self.pop_mark();
+    /// Parse every `.c1` (or `.c-1`) file in `dir`, see `parse_file`. The files are independent,
+    /// so they are split across worker threads; the results are ordered by path. If `dir` cannot
+    /// be read, the only result is an error for `dir` itself.
+    pub fn parse_dir<P: AsRef<Path>>(dir: P) -> Vec<(PathBuf, ParseResult)> {
+        let dir = dir.as_ref();
+        let mut paths: Vec<PathBuf> = match fs::read_dir(dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| {
+                    path.is_file()
+                        && path
+                            .extension()
+                            .is_some_and(|ext| ext == "c1" || ext == "c-1")
+                })
+                .collect(),
+            Err(err) => return vec![(dir.to_path_buf(), Err(io_error(dir, err)))],
+        };
+        paths.sort();
+
+        let workers = thread::available_parallelism().map_or(1, |n| n.get());
+        let chunk_size = paths.len().div_ceil(workers).max(1);
+
+        thread::scope(|scope| {
+            let handles: Vec<_> = paths
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|path| (path.clone(), Self::parse_file(path)))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        })
+    }
 
-        res
+    /// Read and parse a single file. The message of any error starts with the file's path; if the
+    /// file cannot be read, the error is located at line 1 and names no tokens.
+    pub fn parse_file<P: AsRef<Path>>(path: P) -> ParseResult {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|err| io_error(path, err))?;
+        Self::parse(&text).map_err(|err| ParseError {
+            message: format!("{}: {}", path.display(), err.message),
+            ..err
+        })
     }
 
-    fn ifstatement(&mut self) -> ParseResult {
-        // <KW_IF> "(" assignment ")" block
-        self.check_and_eat_token(&C1Token::KwIf, r#"Expected "if""#)?;
-        self.check_and_eat_token(&C1Token::LeftParenthesis, r#"Expected "(""#)?;
-        self.assignment()?;
-        self.check_and_eat_token(&C1Token::RightParenthesis, r#"Expected ")""#)?;
-        self.block()
+    /// Create a parser for `text` that is driven by calling `program`. Unlike the `parse`
+    /// functions, this allows to reuse the parser for further inputs with `reset`.
+    pub fn initialize_parser(text: &str) -> C1Parser<'_> {
+        C1Parser {
+            lexer: C1Lexer::new(text),
+            allow_asm: false,
+            allow_gcc_attributes: false,
+            backtrack_log: None,
+            errors: None,
+            memo: HashMap::new(),
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            depth_exceeded: false,
+            errors_built: Cell::new(0),
+        }
     }
 
-    fn returnstatement(&mut self) -> ParseResult {
-        self.check_and_eat_token(&C1Token::KwReturn, r#"Expected "return""#)?;
-        let _ = self.assignment(); // optional
-        Ok(())
+    /// Start over on a new input. Settings are kept, while memoized results, the nesting depth
+    /// and recorded errors and backtracks of the previous input are discarded.
+    pub fn reset(&mut self, text: &'a str) {
+        self.lexer.reset(text);
+        self.memo.clear();
+        self.depth = 0;
+        self.depth_exceeded = false;
+        self.errors_built.set(0);
+        if let Some(errors) = &mut self.errors {
+            errors.clear();
+        }
+        if let Some(log) = &mut self.backtrack_log {
+            log.clear();
+        }
     }
 
-    fn printf(&mut self) -> ParseResult {
-        self.check_and_eat_token(&C1Token::KwPrintf, r#"Expected "printf""#)?;
-        self.check_and_eat_token(&C1Token::LeftParenthesis, r#"Expected "(""#)?;
-        self.assignment()?;
-        self.check_and_eat_token(&C1Token::RightParenthesis, r#"Expected ")""#)
-    }
-
-    fn p_type(&mut self) -> ParseResult {
-        self.any_match_and_eat(
-            &[
-                C1Token::KwBoolean,
-                C1Token::KwFloat,
-                C1Token::KwInt,
-                C1Token::KwVoid,
-            ],
-            "Expected type",
-        )
+    /// program ::= ( functiondefinition | declaration )* <EOF>
+    ///
+    /// Runs on a thread of its own with a stack of `STACK_SIZE`, so that input nested up to the
+    /// depth limit fits, no matter how much stack the calling thread has left.
+    pub fn program(&mut self) -> Result<Program, ParseError> {
+        thread::scope(|scope| {
+            thread::Builder::new()
+                .stack_size(STACK_SIZE)
+                .spawn_scoped(scope, || self.items())
+                .expect("failed to spawn the parser thread")
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+        })
     }
 
-    fn statassignment(&mut self) -> ParseResult {
-        self.check_and_eat_token(&C1Token::Identifier, r#"Expected <ID>"#)
-            .and_then(|_| self.check_and_eat_token(&C1Token::Assign, r#"Expected "=""#))
-            .and_then(|_| self.assignment())
+    fn items(&mut self) -> Result<Program, ParseError> {
+        let mut items = vec![];
+        while self.current_token().is_some() {
+            let item = if self.at_global_declaration() {
+                self.declaration().map(Item::Global)
+            } else {
+                self.function_definition().map(Item::Function)
+            };
+            match item {
+                Ok(item) => items.push(item),
+                Err(err) if self.errors.is_some() => {
+                    self.recover(err);
+                    // a stray "}" ends no statement here, so it has to be skipped as well
+                    if self.current_matches(&C1Token::RightBrace) {
+                        self.advance();
+                    }
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(Program { items })
     }
 
-    fn assignment(&mut self) -> ParseResult {
-        let m = self.mark();
+    /// Both a function definition and a global declaration start with `type <ID>`, only the token
+    /// after the identifier tells them apart
+    fn at_global_declaration(&mut self) -> bool {
+        matches!(
+            self.peek_nth(2),
+            Some(C1Token::Assign | C1Token::Semicolon | C1Token::Comma | C1Token::LeftBracket)
+        )
+    }
 
-        let res = self
-            .check_and_eat_token(&C1Token::Identifier, r#"Expected "<ID>""#)
-            .and_then(|_| self.check_and_eat_token(&C1Token::Assign, r#"Expected "=""#))
-            .and_then(|_| self.assignment())
-            .or_else(|_| {
-                self.undo(m);
-                self.expr()
-            });
+    /// Return the name and top-level statement count of the function with the most statements
+    pub fn largest_function(text: &str) -> Result<Option<(String, usize)>, String> {
+        let program = Self::parse_to_ast(text).map_err(|err| err.to_string())?;
+        let mut largest: Option<(String, usize)> = None;
+
+        for item in program.items {
+            if let Item::Function(function) = item {
+                let count = function.body.len();
+                if largest.as_ref().is_none_or(|(_, max)| count > *max) {
+                    largest = Some((function.name, count));
+                }
+            }
+        }
 
-        self.pop_mark();
+        Ok(largest)
+    }
 
-        res
+    /// Return the signature of the function called `name`, `None` if `text` defines no such
+    /// function
+    pub fn function_signature(text: &str, name: &str) -> Result<Option<Signature>, String> {
+        let program = Self::parse_to_ast(text).map_err(|err| err.to_string())?;
+        let signature = program.items.into_iter().find_map(|item| match item {
+            Item::Function(function) if function.name == name => Some(Signature {
+                return_type: function.return_type,
+                parameter_types: function.parameters.iter().map(|p| p.ty).collect(),
+                parameter_names: function.parameters.into_iter().map(|p| p.name).collect(),
+            }),
+            _ => None,
+        });
+        Ok(signature)
     }
 
-    fn expr(&mut self) -> ParseResult {
-        self.simpexpr()?;
-        let m = self.mark();
+    /// Return a hash over the sequence of token types in `text`, ignoring identifier and literal
+    /// text as well as whitespace and comments. Structurally identical programs hash equally.
+    pub fn structural_hash(text: &str) -> Result<u64, String> {
+        // FNV-1a, so the value does not depend on the standard library's hasher
+        const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+        const FNV_PRIME: u64 = 0x100000001b3;
+
+        let mut lexer = C1Lexer::new(text);
+        let mut hash = FNV_OFFSET;
+
+        while let Some(token) = lexer.current_token() {
+            if token == C1Token::Error {
+                return Err(format!(
+                    "Invalid token '{}' at line {}",
+                    lexer.current_text().unwrap(),
+                    lexer.current_line_number().unwrap()
+                ));
+            }
+            hash = (hash ^ token as u64).wrapping_mul(FNV_PRIME);
+            lexer.advance();
+        }
 
-        let ops = [
-            C1Token::Equal,
-            C1Token::NotEqual,
-            C1Token::LessEqual,
-            C1Token::GreaterEqual,
-            C1Token::Less,
-            C1Token::Greater,
-        ];
+        Ok(hash)
+    }
 
-        let _ = self
-            .any_match_and_eat(&ops, "")
-            .and_then(|_| self.simpexpr())
-            .or_else(|_| -> ParseResult {
-                self.undo(m);
-                Ok(())
-            }); // optional
+    /// Parse `text` and return its problems as a JSON array of LSP `Diagnostic` objects: every
+    /// syntax error as an error, and for a program without syntax errors the unreachable
+    /// statements and undeclared variables as warnings. A range covers the offending token or
+    /// node; at the end of the input it is empty and placed after the last character.
+    pub fn lsp_diagnostics(text: &str) -> String {
+        let mut diagnostics = vec![];
+        match Self::parse_all(text) {
+            Err(errors) => {
+                for err in errors {
+                    let (start, end) = error_range(text, &err);
+                    diagnostics.push(lsp_diagnostic(start, end, 1, &err.message));
+                }
+            }
+            Ok(()) => {
+                let program = Self::parse_to_ast(text).expect("parse_all found no error");
+                let range = |span: Span| {
+                    let end = Span {
+                        start: span.end,
+                        end: span.end,
+                    };
+                    (span.line_column(text), end.line_column(text))
+                };
+                for span in analysis::unreachable_code(&program) {
+                    let (start, end) = range(span);
+                    diagnostics.push(lsp_diagnostic(start, end, 2, "Unreachable code"));
+                }
+                for variable in analysis::undeclared_variables(&program, text) {
+                    let (start, end) = range(variable.span);
+                    let message = format!("Undeclared variable '{}'", variable.name);
+                    diagnostics.push(lsp_diagnostic(start, end, 2, &message));
+                }
+            }
+        }
+        format!("[{}]", diagnostics.join(","))
+    }
 
-        self.pop_mark();
+    /// Parse a function definition; its body holds the top-level statements
+    fn function_definition(&mut self) -> Result<Function, ParseError> {
+        let start = self.node_start();
+        self.gcc_attributes()
+            .and_then(|_| self.function_head())
+            .and_then(|(return_type, name, parameters)| {
+                self.check_and_eat_token(&C1Token::LeftBrace, r#"Expected "{""#)?;
+                let body = self.statementlist()?;
+                self.check_and_eat_token(&C1Token::RightBrace, r#"Expected "}""#)?;
+                Ok(Function {
+                    return_type,
+                    name,
+                    parameters,
+                    body,
+                    span: self.span_from(start),
+                })
+            })
+            .map_err(|err| self.context(err, ", in function definition"))
+    }
 
-        Ok(())
+    /// type <ID> "(" parameterlist ")"
+    fn function_head(&mut self) -> Result<(Type, String, Vec<Parameter>), ParseError> {
+        let return_type = self.p_type()?;
+        let name = self.identifier("Expected function name")?;
+        let opened_at = self.open_parenthesis(r#"Expected "(""#)?;
+        let parameters = self.parameterlist()?;
+        self.close_parenthesis(opened_at)?;
+        Ok((return_type, name, parameters))
     }
 
-    fn simpexpr(&mut self) -> ParseResult {
-        let _ = self.check_and_eat_token(&C1Token::Minus, ""); // optional
-        self.term().map_err(|err| err + ", in simpexpr")?;
-        let mut m = self.mark();
+    /// gcc_attributes ::= ( <KW_ATTRIBUTE> "(" "(" ... ")" ")" )*
+    ///
+    /// The contents of an attribute are not interpreted, only its parentheses have to be
+    /// balanced. Nothing is consumed unless `allow_gcc_attributes` is set.
+    fn gcc_attributes(&mut self) -> ParseResult {
+        while self.allow_gcc_attributes && self.current_matches(&C1Token::KwAttribute) {
+            self.advance();
+            let outer = self.open_parenthesis(r#"Expected "(" after "__attribute__""#)?;
+            let inner = self.open_parenthesis(r#"Expected "((" after "__attribute__""#)?;
+            let mut open = vec![outer, inner];
+            while let Some(&opened_at) = open.last() {
+                match self.current_token() {
+                    Some(C1Token::LeftParenthesis) => {
+                        open.push(self.open_parenthesis("")?);
+                    }
+                    Some(C1Token::RightParenthesis) => {
+                        self.close_parenthesis(opened_at)?;
+                        open.pop();
+                    }
+                    Some(_) => self.advance(),
+                    None => return self.close_parenthesis(opened_at),
+                }
+            }
+        }
+        Ok(())
+    }
 
-        while let Ok(_) = self
-            .any_match_and_eat(&[C1Token::Plus, C1Token::Minus, C1Token::Or], "")
-            .and_then(|_| self.term())
+    /// parameterlist ::= <KW_VOID> | ( type <ID> ( "," type <ID> )* )?
+    ///
+    /// A lone `void` means "explicitly no parameters", so `f(void)` is equivalent to `f()`.
+    fn parameterlist(&mut self) -> Result<Vec<Parameter>, ParseError> {
+        if self.current_matches(&C1Token::KwVoid)
+            && self.peek_token() == Some(C1Token::RightParenthesis)
         {
-            self.pop_mark();
-            m = self.mark();
+            self.advance();
+            return Ok(vec![]);
+        }
+        if self.current_matches(&C1Token::RightParenthesis) {
+            return Ok(vec![]);
         }
 
-        self.undo(m);
-        self.pop_mark();
+        let mut parameters = vec![self.parameter()?];
+        while self.current_matches(&C1Token::Comma) {
+            self.advance();
+            parameters.push(self.parameter()?);
+        }
+        Ok(parameters)
+    }
 
-        Ok(())
+    fn parameter(&mut self) -> Result<Parameter, ParseError> {
+        let start = self.node_start();
+        let ty = self.p_type()?;
+        let name = self.identifier("Expected parameter name")?;
+        Ok(Parameter {
+            ty,
+            name,
+            span: self.span_from(start),
+        })
     }
 
-    fn term(&mut self) -> ParseResult {
-        self.factor().map_err(|err| err + ", in term")?;
-        let mut m = self.mark();
+    /// functioncall ::= <ID> "(" ( assignment ( "," assignment )* )? ")"
+    fn functioncall(&mut self) -> Result<Expr, ParseError> {
+        let start = self.node_start();
+        self.identifier("Expected <ID>")
+            .and_then(|name| {
+                let opened_at = self.open_parenthesis(r#"Expected "(""#)?;
+                let mut arguments = vec![];
+                if !self.current_matches(&C1Token::RightParenthesis) {
+                    arguments.push(self.assignment()?);
+                    while self.current_matches(&C1Token::Comma) {
+                        self.advance();
+                        arguments.push(self.assignment()?);
+                    }
+                }
+                self.close_parenthesis(opened_at)?;
+                Ok(self.expr_since(start, ExprKind::Call { name, arguments }))
+            })
+            .map_err(|err| self.context(err, ", in functioncall"))
+    }
 
-        while let Ok(_) = self
-            .any_match_and_eat(&[C1Token::Asterisk, C1Token::Slash, C1Token::And], "")
-            .and_then(|_| self.factor())
-        {
-            self.pop_mark();
-            m = self.mark();
+    /// statementlist ::= block*
+    ///
+    /// The list ends at the "}" or the end of input, anything else has to be a statement.
+    fn statementlist(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        let mut statements = vec![];
+        while !matches!(self.current_token(), None | Some(C1Token::RightBrace)) {
+            match self.block() {
+                Ok(statement) => statements.push(statement),
+                Err(err) if self.errors.is_some() => self.recover(err),
+                Err(err) => return Err(err),
+            }
         }
+        Ok(statements)
+    }
 
-        self.undo(m);
-        self.pop_mark();
+    fn block(&mut self) -> Result<Stmt, ParseError> {
+        self.nested(Self::parse_block)
+    }
 
-        Ok(())
+    /// block ::= "{" statementlist "}" | statement
+    fn parse_block(&mut self) -> Result<Stmt, ParseError> {
+        if !self.current_matches(&C1Token::LeftBrace) {
+            return self.statement();
+        }
+        let start = self.node_start();
+        self.advance();
+        let statements = self.statementlist()?;
+        self.check_and_eat_token(&C1Token::RightBrace, r#"Expected "}""#)?;
+        Ok(self.stmt_since(start, StmtKind::Block(statements)))
     }
 
-    fn factor(&mut self) -> ParseResult {
+    /// Parse a statement. Its span includes the trailing semicolon, if there is one.
+    fn statement(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.node_start();
+        let recovered = self.recovered_count();
         let m = self.mark();
-
         let res = self
-            .check_and_eat_token(&C1Token::ConstInt, "")
-            .or_else(|_| {
-                self.undo(m);
-                self.check_and_eat_token(&C1Token::ConstFloat, "")
+            .parse_statement()
+            .map(|mut statement| {
+                statement.span = self.span_from(start);
+                statement
             })
-            .or_else(|_| {
+            .map_err(|err| {
                 self.undo(m);
-                self.check_and_eat_token(&C1Token::ConstBoolean, "")
-            })
-            .or_else(|_| {
-                self.undo(m);
-                self.functioncall()
-            })
-            .or_else(|_| {
-                self.undo(m);
-                self.check_and_eat_token(&C1Token::Identifier, "")
-            })
-            .or_else(|_| {
-                self.undo(m);
-                self.check_and_eat_token(&C1Token::LeftParenthesis, "Expected <FACTOR>")?;
-                self.assignment()?;
-                self.check_and_eat_token(&C1Token::RightParenthesis, "Expected ')'")
-            })
-            .or_else(|err| {
-                self.undo(m);
-                Err(err)
+                // errors in nested blocks are reported again as part of this one
+                self.discard_recovered(recovered);
+                if self.depth_exceeded {
+                    return err;
+                }
+                self.invalid_assignment_target().unwrap_or(err)
             });
 
         self.pop_mark();
@@ -279,178 +598,1216 @@ impl<'a> C1Parser<'a> {
         res
     }
 
-    /// Check whether the current token is equal to the given token. If yes, consume it, otherwise
-    /// return an error with the given error message
-    fn check_and_eat_token(&mut self, token: &C1Token, reason: &str) -> ParseResult {
-        if self.current_matches(token) {
+    /// The kind of statement is told apart by its first token, and for one starting with an
+    /// identifier by the token after it. So a failing statement does not try the others, and its
+    /// error is the one of the statement that was actually written.
+    fn parse_statement(&mut self) -> Result<Stmt, ParseError> {
+        match self.current_token() {
+            Some(C1Token::KwIf) => self.ifstatement(),
+            Some(C1Token::KwWhile) => self.whilestatement(),
+            Some(C1Token::KwDo) => self.dowhilestatement(),
+            Some(C1Token::KwFor) => self.forstatement(),
+            _ => self.simple_statement(),
+        }
+    }
+
+    /// A statement that does not contain other statements. Kept apart from `parse_statement`,
+    /// which sits on the stack once per nesting level, to keep its frame small.
+    fn simple_statement(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.node_start();
+        let (statement, after) = match self.current_token() {
+            Some(C1Token::KwBoolean | C1Token::KwFloat | C1Token::KwInt | C1Token::KwVoid) => {
+                let declaration = self.declaration()?;
+                return Ok(self.stmt_since(start, StmtKind::Declaration(declaration)));
+            }
+            // empty statement; it always consumes a token, so statementlist cannot get stuck
+            Some(C1Token::Semicolon) => {
+                self.advance();
+                return Ok(self.stmt_since(start, StmtKind::Empty));
+            }
+            Some(C1Token::KwReturn) => (self.returnstatement()?, "return statement"),
+            Some(C1Token::KwBreak) => {
+                self.advance();
+                (self.stmt_since(start, StmtKind::Break), "break")
+            }
+            Some(C1Token::KwContinue) => {
+                self.advance();
+                (self.stmt_since(start, StmtKind::Continue), "continue")
+            }
+            Some(C1Token::KwPrintf) => (self.printf()?, "printf"),
+            Some(C1Token::KwAsm) => (self.asmstatement()?, "asm statement"),
+            Some(C1Token::Increment | C1Token::Decrement) => {
+                (self.incdecstatement()?, "increment/decrement")
+            }
+            Some(C1Token::Identifier) => match self.peek_token() {
+                Some(C1Token::Increment | C1Token::Decrement) => {
+                    (self.incdecstatement()?, "increment/decrement")
+                }
+                Some(C1Token::LeftParenthesis) => {
+                    let call = self.functioncall()?;
+                    (
+                        self.stmt_since(start, StmtKind::Expr(call)),
+                        "function call",
+                    )
+                }
+                _ => (self.statassignment()?, "stat assignment"),
+            },
+            _ => return Err(self.unexpected_token("Expected statement")),
+        };
+        self.check_and_eat_token_with(&C1Token::Semicolon, || {
+            format!("Expected semicolon after {}", after)
+        })?;
+        Ok(statement)
+    }
+
+    /// Detect an assignment like `5 = x` or `foo() = 1` whose left-hand side is not an
+    /// identifier, so that it can be reported specifically instead of as a generic mismatch.
+    fn invalid_assignment_target(&mut self) -> Option<ParseError> {
+        let m = self.mark();
+        let at_assignment = |parser: &Self| {
+            ASSIGNMENT_OPERATORS
+                .iter()
+                .any(|op| parser.current_matches(op))
+        };
+        let valid_target =
+            self.eat(&C1Token::Identifier) && self.index().is_ok() && at_assignment(self);
+        self.undo(m);
+        let invalid = !valid_target && self.unary().is_ok() && at_assignment(self);
+        self.undo(m);
+        self.pop_mark();
+
+        // back at the start of the target, which the error points at
+        invalid.then(|| self.unexpected_token("invalid assignment target"))
+    }
+
+    /// declaration ::= type declarator ( "," declarator )* ";"
+    fn declaration(&mut self) -> Result<Declaration, ParseError> {
+        let start = self.node_start();
+        let ty = self.p_type()?;
+        let mut variables = vec![self.declarator()?];
+        while self.current_matches(&C1Token::Comma) {
             self.advance();
-            Ok(())
-        } else {
-            let err = match self.current_token() {
-                None => format!("{}. Reached EOF", reason),
-                Some(_) => format!(
-                    "Unexpected token: {} \n at line {:?} while trying to parse: '{}'",
-                    reason,
-                    self.current_line_number().unwrap(),
-                    self.current_text().unwrap()
-                ),
-            };
-            Err(err)
+            variables.push(self.declarator()?);
         }
+        self.check_and_eat_token(&C1Token::Semicolon, "Expected semicolon after declaration")?;
+        Ok(Declaration {
+            ty,
+            variables,
+            span: self.span_from(start),
+        })
     }
 
-    /// Check whether the given token matches the current token
-    fn current_matches(&self, token: &C1Token) -> bool {
-        match &self.current_token() {
-            None => false,
-            Some(current) => current == token,
+    /// declarator ::= <ID> ( "[" <CONST_INT> "]" | "=" assignment )?
+    fn declarator(&mut self) -> Result<Variable, ParseError> {
+        let start = self.node_start();
+        let name = self.identifier("Expected variable name")?;
+        let mut variable = Variable {
+            name,
+            array_size: None,
+            initializer: None,
+            span: Span::default(),
+        };
+        if self.current_matches(&C1Token::LeftBracket) {
+            self.advance();
+            let size = self.current_text().unwrap_or_default().to_string();
+            self.check_and_eat_token(&C1Token::ConstInt, "Expected array size")?;
+            let value = int_literal(&size).map_err(|message| self.error_at_previous(message))?;
+            let size = usize::try_from(value)
+                .map_err(|_| self.error_at_previous(format!("Invalid array size '{}'", size)))?;
+            variable.array_size = Some(size);
+            self.check_and_eat_token(&C1Token::RightBracket, r#"Expected "]""#)?;
+        } else if self.current_matches(&C1Token::Assign) {
+            self.advance();
+            variable.initializer = Some(self.assignment()?);
         }
+        variable.span = self.span_from(start);
+        Ok(variable)
     }
 
-    /// Check whether any of the tokens matches the current token, then consume it
-    fn any_match_and_eat(&mut self, token: &[C1Token], reason: &str) -> ParseResult {
-        if token
-            .iter()
-            .any(|t| self.check_and_eat_token(t, "").is_ok())
-        {
-            Ok(())
-        } else {
-            let err = match self.current_token() {
-                None => format!("{}. Reached EOF", reason),
-                Some(_) => format!(
-                    "Unexpected token: {} \n at line {:?} while trying to parse: '{}'",
-                    reason,
-                    self.current_line_number().unwrap(),
-                    self.current_text().unwrap()
-                ),
-            };
-            Err(err)
+    /// ifstatement ::= <KW_IF> "(" assignment ")" block ( <KW_ELSE> block )?
+    ///
+    /// The `else` is consumed greedily, so a dangling `else` belongs to the nearest `if`.
+    fn ifstatement(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.node_start();
+        self.check_and_eat_token(&C1Token::KwIf, r#"Expected "if""#)?;
+        let condition = self.condition()?;
+        let then_branch = Box::new(self.block()?);
+        let mut else_branch = None;
+        if self.current_matches(&C1Token::KwElse) {
+            self.advance();
+            else_branch = Some(Box::new(self.block()?));
         }
+        let kind = StmtKind::If {
+            condition,
+            then_branch,
+            else_branch,
+        };
+        Ok(self.stmt_since(start, kind))
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::parser::{C1Parser, ParseResult};
+    /// "(" assignment ")", the condition of an if statement or a loop
+    fn condition(&mut self) -> Result<Expr, ParseError> {
+        let opened_at = self.open_parenthesis(r#"Expected "(""#)?;
+        let condition = self.assignment()?;
+        self.close_parenthesis(opened_at)?;
+        Ok(condition)
+    }
 
-    fn call_method<'a, F>(parse_method: F, text: &'static str) -> ParseResult
-    where
-        F: Fn(&mut C1Parser<'a>) -> ParseResult,
-    {
-        let mut parser = C1Parser::initialize_parser(text);
-        if let Err(message) = parse_method(&mut parser) {
-            eprintln!("Parse Error: {}", message);
-            Err(message)
+    fn whilestatement(&mut self) -> Result<Stmt, ParseError> {
+        // <KW_WHILE> "(" assignment ")" block
+        let start = self.node_start();
+        self.check_and_eat_token(&C1Token::KwWhile, r#"Expected "while""#)?;
+        let condition = self.condition()?;
+        let body = Box::new(self.block()?);
+        Ok(self.stmt_since(start, StmtKind::While { condition, body }))
+    }
+
+    fn dowhilestatement(&mut self) -> Result<Stmt, ParseError> {
+        // <KW_DO> block <KW_WHILE> "(" assignment ")" ";"
+        let start = self.node_start();
+        self.check_and_eat_token(&C1Token::KwDo, r#"Expected "do""#)?;
+        let body = Box::new(self.block()?);
+        self.check_and_eat_token(&C1Token::KwWhile, r#"Expected "while" after do block"#)?;
+        let condition = self.condition()?;
+        self.check_and_eat_token(
+            &C1Token::Semicolon,
+            "Expected semicolon after do-while loop",
+        )?;
+        Ok(self.stmt_since(start, StmtKind::DoWhile { body, condition }))
+    }
+
+    /// forstatement ::= <KW_FOR> "(" ( declaration | statassignment? ";" ) assignment ";"
+    ///                  statassignment? ")" block
+    ///
+    /// Init and update may be left out, the condition and both semicolons are required. A
+    /// variable declared in the init is only in scope in the loop.
+    fn forstatement(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.node_start();
+        self.check_and_eat_token(&C1Token::KwFor, r#"Expected "for""#)?;
+        let (init, condition, update) = self.for_header()?;
+        let body = Box::new(self.block()?);
+        let kind = StmtKind::For {
+            init,
+            condition,
+            update,
+            body,
+        };
+        Ok(self.stmt_since(start, kind))
+    }
+
+    /// The parenthesized init, condition and update of a for loop
+    fn for_header(&mut self) -> Result<ForHeader, ParseError> {
+        let opened_at = self.open_parenthesis(r#"Expected "(""#)?;
+        let mut init = None;
+        if let Some(C1Token::KwBoolean | C1Token::KwFloat | C1Token::KwInt | C1Token::KwVoid) =
+            self.current_token()
+        {
+            let init_start = self.node_start();
+            let declaration = self.declaration()?;
+            let kind = StmtKind::Declaration(declaration);
+            init = Some(Box::new(self.stmt_since(init_start, kind)));
         } else {
-            println!("{:?}", parser.current_token());
-            Ok(())
+            if !self.current_matches(&C1Token::Semicolon) {
+                init = Some(Box::new(self.statassignment()?));
+            }
+            self.check_and_eat_token(&C1Token::Semicolon, "Expected semicolon after for init")?;
+        }
+        let condition = self.assignment()?;
+        self.check_and_eat_token(
+            &C1Token::Semicolon,
+            "Expected semicolon after for condition",
+        )?;
+        let mut update = None;
+        if !self.current_matches(&C1Token::RightParenthesis) {
+            update = Some(Box::new(self.statassignment()?));
         }
+        self.close_parenthesis(opened_at)?;
+        Ok((init, condition, update))
     }
 
-    #[test]
-    fn parse_empty_program() {
-        let result = C1Parser::parse("");
-        assert_eq!(result, Ok(()));
+    fn returnstatement(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.node_start();
+        self.check_and_eat_token(&C1Token::KwReturn, r#"Expected "return""#)?;
+        // optional, anything that cannot start one is left for the semicolon check
+        let mut value = None;
+        if self.at_expression_start() {
+            value = Some(self.assignment()?);
+        }
+        Ok(self.stmt_since(start, StmtKind::Return(value)))
+    }
 
-        let result = C1Parser::parse("   ");
-        assert_eq!(result, Ok(()));
+    /// Whether the current token can be the first one of an expression
+    fn at_expression_start(&self) -> bool {
+        matches!(
+            self.current_token(),
+            Some(
+                C1Token::Identifier
+                    | C1Token::ConstInt
+                    | C1Token::ConstFloat
+                    | C1Token::ConstBoolean
+                    | C1Token::ConstChar
+                    | C1Token::ConstString
+                    | C1Token::LeftParenthesis
+                    | C1Token::Minus
+                    | C1Token::Plus
+                    | C1Token::Decrement
+                    | C1Token::Not
+            )
+        )
+    }
 
-        let result = C1Parser::parse("// This is a valid comment!");
-        assert_eq!(result, Ok(()));
+    /// printf ::= <KW_PRINTF> "(" assignment ( "," assignment )* ")"
+    ///
+    /// The first argument is usually the format string, but any expression is accepted.
+    fn printf(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.node_start();
+        self.check_and_eat_token(&C1Token::KwPrintf, r#"Expected "printf""#)?;
+        let opened_at = self.open_parenthesis(r#"Expected "(""#)?;
+        let mut arguments = vec![self.assignment()?];
+        while self.current_matches(&C1Token::Comma) {
+            self.advance();
+            arguments.push(self.assignment()?);
+        }
+        self.close_parenthesis(opened_at)?;
+        Ok(self.stmt_since(start, StmtKind::Printf(arguments)))
+    }
 
-        let result = C1Parser::parse("/* This is a valid comment!\nIn two lines!*/\n");
-        assert_eq!(result, Ok(()));
+    /// asmstatement ::= <KW_ASM> "(" <CONST_STRING> ")"
+    ///
+    /// The assembly string is passed through without being interpreted.
+    fn asmstatement(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.node_start();
+        if !self.allow_asm && self.current_matches(&C1Token::KwAsm) {
+            return Err(self.error(format!(
+                "Inline assembly is not enabled \n at line {:?}",
+                self.current_line_number().unwrap()
+            )));
+        }
+        self.check_and_eat_token(&C1Token::KwAsm, r#"Expected "asm""#)?;
+        let opened_at = self.open_parenthesis(r#"Expected "(""#)?;
+        let literal = self.current_text().unwrap_or_default().to_string();
+        self.check_and_eat_token(&C1Token::ConstString, "Expected assembly string")?;
+        self.close_parenthesis(opened_at)?;
+        let assembly = literal[1..literal.len() - 1].to_string();
+        Ok(self.stmt_since(start, StmtKind::Asm(assembly)))
+    }
 
-        let result = C1Parser::parse("  \n ");
-        assert_eq!(result, Ok(()));
+    /// index ::= ( "[" assignment "]" )?
+    fn index(&mut self) -> Result<Option<Expr>, ParseError> {
+        if !self.current_matches(&C1Token::LeftBracket) {
+            return Ok(None);
+        }
+        self.advance();
+        let index = self.assignment()?;
+        self.check_and_eat_token(&C1Token::RightBracket, r#"Expected "]""#)?;
+        Ok(Some(index))
     }
 
-    #[test]
-    fn fail_invalid_program() {
-        let result = C1Parser::parse("  bool  ");
-        println!("{:?}", result);
-        assert!(result.is_err());
+    fn p_type(&mut self) -> Result<Type, ParseError> {
+        let ty = match self.current_token() {
+            Some(C1Token::KwBoolean) => Type::Bool,
+            Some(C1Token::KwFloat) => Type::Float,
+            Some(C1Token::KwInt) => Type::Int,
+            Some(C1Token::KwVoid) => Type::Void,
+            _ => return Err(self.unexpected_token("Expected type")),
+        };
+        self.advance();
+        Ok(ty)
+    }
 
-        let result = C1Parser::parse("int x = 0;");
-        println!("{:?}", result);
-        assert!(result.is_err());
+    /// incdecstatement ::= <ID> ( "++" | "--" ) | ( "++" | "--" ) <ID>
+    ///
+    /// Both the postfix and the prefix form are accepted. They are statements only, not
+    /// expressions, so `x = i++` is rejected.
+    fn incdecstatement(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.node_start();
+        let prefix = self.incdec_operator();
+        let target = self.identifier("Expected <ID>")?;
+        let op = match prefix {
+            Some(op) => op,
+            None => self
+                .incdec_operator()
+                .ok_or_else(|| self.unexpected_token(r#"Expected "++" or "--""#))?,
+        };
+        let kind = StmtKind::IncDec {
+            target,
+            op,
+            prefix: prefix.is_some(),
+        };
+        Ok(self.stmt_since(start, kind))
+    }
 
-        let result = C1Parser::parse("// A valid comment\nInvalid line.");
-        println!("{:?}", result);
-        assert!(result.is_err());
+    /// Consume a "++" or "--" if there is one
+    fn incdec_operator(&mut self) -> Option<IncDecOp> {
+        let op = match self.current_token() {
+            Some(C1Token::Increment) => IncDecOp::Increment,
+            Some(C1Token::Decrement) => IncDecOp::Decrement,
+            _ => return None,
+        };
+        self.advance();
+        Some(op)
     }
 
-    #[test]
-    fn valid_function() {
-        // let result = C1Parser::parse("  void foo() {}  ");
-        // assert!(result.is_ok(), "Parse result: {}", result.err().unwrap());
+    /// statassignment ::= <ID> index? ( "=" | "+=" | "-=" | "*=" | "/=" | "%=" ) assignment
+    fn statassignment(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.node_start();
+        let target = self.identifier(r#"Expected <ID>"#)?;
+        let index = self.index()?;
+        let op = match self.any_match_and_eat(&ASSIGNMENT_OPERATORS, "Expected assignment")? {
+            C1Token::Assign => AssignOp::Assign,
+            C1Token::PlusAssign => AssignOp::Add,
+            C1Token::MinusAssign => AssignOp::Sub,
+            C1Token::StarAssign => AssignOp::Mul,
+            C1Token::SlashAssign => AssignOp::Div,
+            C1Token::PercentAssign => AssignOp::Rem,
+            _ => unreachable!(),
+        };
+        let value = self.assignment()?;
+        let kind = StmtKind::Assign {
+            target,
+            index,
+            op,
+            value,
+        };
+        Ok(self.stmt_since(start, kind))
+    }
 
-        let result = C1Parser::parse("int bar() {return 0;}");
-        assert!(result.is_ok(), "Parse result: {}", result.err().unwrap());
+    /// assignment ::= <ID> "=" assignment | ternary
+    ///
+    /// The right-hand side recurses, so `a = b = 1` nests as `a = (b = 1)`.
+    fn assignment(&mut self) -> Result<Expr, ParseError> {
+        let start = self.node_start();
+        if !(self.current_matches(&C1Token::Identifier)
+            && self.peek_token() == Some(C1Token::Assign))
+        {
+            return self.ternary();
+        }
 
-        let result = C1Parser::parse(
-            "float calc() {\n\
-        x = 1.0;
-        y = 2.2;
-        return x + y;
-        \n\
-        }",
-        );
-        assert!(result.is_ok());
+        let target = self.identifier(r#"Expected "<ID>""#)?;
+        self.advance();
+        let value = Box::new(self.nested(Self::assignment)?);
+        Ok(self.expr_since(start, ExprKind::Assign { target, value }))
     }
 
-    #[test]
-    fn fail_invalid_function() {
-        let result = C1Parser::parse("  void foo()) {}  ");
-        println!("{:?}", result);
-        assert!(result.is_err());
+    /// ternary ::= logor ( "?" assignment ":" ternary )?, see `binary_expr` for logor
+    ///
+    /// The else branch recurses, so `a ? b : c ? d : e` nests as `a ? b : (c ? d : e)`.
+    fn ternary(&mut self) -> Result<Expr, ParseError> {
+        let condition = self.binary_expr(LOGOR)?;
+        if !self.current_matches(&C1Token::Question) {
+            return Ok(condition);
+        }
+        self.conditional(condition)
+    }
 
-        let result = C1Parser::parse("const bar() {return 0;}");
-        println!("{:?}", result);
-        assert!(result.is_err());
+    /// The part of a ternary after its condition, kept apart so that the stack used for every
+    /// nested parenthesis does not include it
+    fn conditional(&mut self, condition: Expr) -> Result<Expr, ParseError> {
+        self.advance();
+        let then_value = self.nested(Self::assignment)?;
+        self.check_and_eat_token(&C1Token::Colon, r#"Expected ":" in conditional expression"#)?;
+        let else_value = self.nested(Self::ternary)?;
+        let start = condition.span.start;
+        let kind = ExprKind::Conditional {
+            condition: Box::new(condition),
+            then_value: Box::new(then_value),
+            else_value: Box::new(else_value),
+        };
+        Ok(self.expr_since(start, kind))
+    }
 
-        let result = C1Parser::parse(
-            "int bar() {
-                                                          return 0;
-                                                     int foo() {}",
-        );
-        println!("{:?}", result);
-        assert!(result.is_err());
+    /// Parse a chain of binary operators that bind at least as tightly as `min`, the operands
+    /// being `unary`. The levels, from the loosest to the tightest binding, are
+    ///
+    /// ```text
+    /// logor      ::= logand ( "||" logand )*
+    /// logand     ::= bitor ( "&&" bitor )*
+    /// bitor      ::= bitxor ( "|" bitxor )*
+    /// bitxor     ::= bitand ( "^" bitand )*
+    /// bitand     ::= comparison ( "&" comparison )*
+    /// comparison ::= shiftexpr ( ( "==" | "!=" | "<=" | ">=" | "<" | ">" ) shiftexpr )?
+    /// shiftexpr  ::= simpexpr ( ( "<<" | ">>" ) simpexpr )*
+    /// simpexpr   ::= term ( ( "+" | "-" ) term )*
+    /// term       ::= unary ( ( "*" | "/" | "%" ) unary )*
+    /// ```
+    ///
+    /// As in C, `&&` binds tighter than `||` but looser than the bitwise and comparison operators.
+    /// The operands of one level are grouped from the left, so `a - b - c` becomes
+    /// `(a - b) - c`, while comparisons do not chain. Only an operator without a valid operand
+    /// after it is given back, so the chain is parsed in a single pass.
+    ///
+    /// The levels are climbed in one function instead of one rule each, which keeps the stack
+    /// used per nested parenthesis small.
+    fn binary_expr(&mut self, min: u8) -> Result<Expr, ParseError> {
+        let mut left = self.unary().map_err(|err| {
+            let err = self.context(err, ", in term");
+            self.context(err, ", in simpexpr")
+        })?;
+        // the loosest operator of `left`, no tighter one can take it as its left operand
+        let mut left_level = u8::MAX;
+
+        while let Some(level) = self.current_token().and_then(binary_level) {
+            let accepted = match level {
+                COMPARISON => left_level > level,
+                _ => left_level >= level,
+            };
+            if level < min || !accepted {
+                break;
+            }
+            let m = self.mark();
+            let op = self.current_token().unwrap();
+            self.advance();
+            match self.binary_expr(level + 1) {
+                Ok(right) => {
+                    left = binary(op, left, right);
+                    left_level = level;
+                    self.pop_mark();
+                }
+                Err(err) if self.depth_exceeded => return Err(err),
+                Err(_) => {
+                    self.undo(m);
+                    self.pop_mark();
+                    break;
+                }
+            }
+        }
 
-        let result = C1Parser::parse(
-            "float calc(int invalid) {\n\
-        int x = 1.0;
-        int y = 2.2;
-        return x + y;
-        \n\
-        }",
-        );
-        println!("{:?}", result);
-        assert!(result.is_err());
+        Ok(left)
     }
 
-    #[test]
-    fn valid_functioncall() {
-        assert!(call_method(C1Parser::functioncall, "foo()").is_ok());
-        assert!(call_method(C1Parser::functioncall, "foo( )").is_ok());
-        assert!(call_method(C1Parser::functioncall, "bar23( )").is_ok());
+    /// unary ::= ( "-" | "+" | "--" | "!" ) unary | factor
+    ///
+    /// `--x` lexes as a Decrement token, which in an expression is a double negation `-(-x)`.
+    /// Only as a statement of its own it decrements, see `incdecstatement`.
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        let start = self.node_start();
+        let op = match self.current_token() {
+            Some(C1Token::Minus | C1Token::Decrement) => UnaryOp::Neg,
+            Some(C1Token::Plus) => UnaryOp::Plus,
+            Some(C1Token::Not) => UnaryOp::Not,
+            _ => return self.factor(),
+        };
+        let double = self.current_matches(&C1Token::Decrement);
+        self.advance();
+        let mut operand = Box::new(self.nested(Self::unary)?);
+        if double {
+            // the second "-" of the token
+            let span = Span {
+                start: start + 1,
+                end: operand.span.end,
+            };
+            let kind = ExprKind::Unary { op, operand };
+            operand = Box::new(Expr { kind, span });
+        }
+        Ok(self.expr_since(start, ExprKind::Unary { op, operand }))
     }
 
-    #[test]
-    fn fail_invalid_functioncall() {
-        assert!(call_method(C1Parser::functioncall, "foo)").is_err());
-        assert!(call_method(C1Parser::functioncall, "foo{ )").is_err());
-        assert!(call_method(C1Parser::functioncall, "bar _foo( )").is_err());
+    fn factor(&mut self) -> Result<Expr, ParseError> {
+        self.memoized_factor(|parser| parser.nested(Self::parse_factor))
+    }
+
+    /// factor ::= <CONST_INT> | <CONST_FLOAT> | <CONST_BOOLEAN> | <CONST_CHAR> | <CONST_STRING>
+    ///          | functioncall | <ID> index? | "(" assignment ")"
+    ///
+    /// The alternatives are told apart by the current token and the one after it, so a failing
+    /// factor does not try the others.
+    fn parse_factor(&mut self) -> Result<Expr, ParseError> {
+        let start = self.node_start();
+        let m = self.mark();
+        let kind = if self.current_matches(&C1Token::LeftParenthesis) {
+            self.parenthesized()
+        } else {
+            self.factor_kind()
+        };
+        let res = kind
+            .map(|kind| self.expr_since(start, kind))
+            .inspect_err(|_| self.undo(m));
+
+        self.pop_mark();
+
+        res
+    }
+
+    fn factor_kind(&mut self) -> Result<ExprKind, ParseError> {
+        let literal = match self.current_token() {
+            Some(C1Token::Identifier) if self.peek_token() == Some(C1Token::LeftParenthesis) => {
+                return self.functioncall().map(|call| call.kind);
+            }
+            Some(C1Token::Identifier) => {
+                let name = self.identifier("")?;
+                return Ok(match self.index()? {
+                    Some(index) => ExprKind::Index {
+                        array: name,
+                        index: Box::new(index),
+                    },
+                    None => ExprKind::Variable(name),
+                });
+            }
+            Some(C1Token::ConstString) => {
+                self.current_string_value().unwrap().map(ExprKind::String)
+            }
+            Some(C1Token::ConstInt) => int_literal(self.current_text().unwrap()).map(ExprKind::Int),
+            Some(C1Token::ConstFloat) => {
+                float_literal(self.current_text().unwrap()).map(ExprKind::Float)
+            }
+            Some(C1Token::ConstBoolean) => Ok(ExprKind::Bool(self.current_text() == Some("true"))),
+            Some(C1Token::ConstChar) => {
+                Ok(ExprKind::Char(char_literal(self.current_text().unwrap())))
+            }
+            _ => return Err(self.unexpected_token("Expected <FACTOR>")),
+        };
+        self.advance();
+        literal.map_err(|message| self.error_at_previous(message))
+    }
+
+    /// "(" assignment ")", whose value is the one of the assignment. Apart from `factor_kind`, so
+    /// that the stack used for every nested parenthesis does not include the literal parsing.
+    fn parenthesized(&mut self) -> Result<ExprKind, ParseError> {
+        let opened_at = self.open_parenthesis("")?;
+        let inner = self.assignment()?;
+        self.close_parenthesis(opened_at)?;
+        Ok(inner.kind)
+    }
+
+    /// Run `parse` for a factor, or replay its result if a factor already ran at the current
+    /// position. Backtracking retries the same factors at the same positions, the cache makes
+    /// every one of them run at most once. Only failures and factors without subexpressions are
+    /// stored: replaying a larger result means copying it, which costs as much as parsing it
+    /// again and recurses as deeply as the expression is nested. The result only depends on the
+    /// tokens, so it never has to be invalidated.
+    fn memoized_factor(
+        &mut self,
+        parse: fn(&mut Self) -> Result<Expr, ParseError>,
+    ) -> Result<Expr, ParseError> {
+        let start = self.tokens_consumed();
+        if let Some((result, end)) = self.memo.get(&start) {
+            let (result, end) = (result.clone(), *end);
+            self.lexer.undo(end);
+            return result;
+        }
+        let result = parse(self);
+        let cheap = match &result {
+            Ok(expr) => is_leaf(expr),
+            // unlike other failures, that one depends on where the factor is nested
+            Err(_) => !self.depth_exceeded,
+        };
+        if cheap {
+            self.memo
+                .insert(start, (result.clone(), self.tokens_consumed()));
+        }
+        result
+    }
+
+    /// Run `parse` one level deeper. Blocks, factors and the right-recursive operators are nested
+    /// rules, so every cycle of the grammar passes through one and the depth limit keeps deeply
+    /// nested input from overflowing the stack.
+    fn nested<T>(
+        &mut self,
+        parse: fn(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        if self.depth == self.max_depth {
+            self.depth_exceeded = true;
+            return Err(self.error("maximum nesting depth exceeded".to_string()));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// Byte offset at which the node starting with the current token begins
+    fn node_start(&self) -> usize {
+        match self.current_span() {
+            Some(span) => span.start,
+            None => self.previous_span().map_or(0, |span| span.end),
+        }
+    }
+
+    /// Span from `start` to the end of the last consumed token
+    fn span_from(&self, start: usize) -> Span {
+        let end = self.previous_span().map_or(start, |span| span.end);
+        Span {
+            start,
+            end: end.max(start),
+        }
+    }
+
+    fn stmt_since(&self, start: usize, kind: StmtKind) -> Stmt {
+        Stmt {
+            kind,
+            span: self.span_from(start),
+        }
+    }
+
+    fn expr_since(&self, start: usize, kind: ExprKind) -> Expr {
+        Expr {
+            kind,
+            span: self.span_from(start),
+        }
+    }
+
+    /// Consume an identifier and return its name
+    fn identifier(&mut self, reason: &str) -> Result<String, ParseError> {
+        let name = self.current_text().unwrap_or_default().to_string();
+        self.check_and_eat_token(&C1Token::Identifier, reason)?;
+        Ok(name)
+    }
+
+    /// Reset the lexer to `marker`, recording the backtrack if a log was requested
+    fn undo(&mut self, marker: usize) {
+        let position = self.lexer.tokens_consumed();
+        if let Some(log) = self.backtrack_log.as_mut().filter(|_| marker < position) {
+            log.push((position, marker));
+        }
+        self.lexer.undo(marker);
+    }
+
+    /// Record `err` and skip the statement it occurred in, see `synchronize`
+    fn recover(&mut self, err: ParseError) {
+        if let Some(errors) = &mut self.errors {
+            errors.push(err);
+        }
+        self.depth_exceeded = false;
+        self.synchronize();
+    }
+
+    /// Discard the current token and everything after it up to the end of the statement: a ";" or
+    /// a whole "{ ... }" group, which are consumed as well, or a "}" or a keyword that starts the
+    /// next statement, which are kept. Tokens inside a group do not end the statement.
+    /// At least one token is discarded, so a statement that fails at its first token cannot stop
+    /// the parser from making progress.
+    fn synchronize(&mut self) {
+        let mut depth = 0usize;
+        let mut first = true;
+        while let Some(token) = self.current_token() {
+            match token {
+                C1Token::LeftBrace => depth += 1,
+                C1Token::RightBrace if depth > 0 => depth -= 1,
+                _ if first => {}
+                C1Token::RightBrace => return,
+                _ if depth == 0 && STATEMENT_KEYWORDS.contains(&token) => return,
+                _ => {}
+            }
+            first = false;
+            self.advance();
+            if depth == 0 && matches!(token, C1Token::Semicolon | C1Token::RightBrace) {
+                return;
+            }
+        }
+    }
+
+    /// Append a note on the enclosing rule to `err`, unless it is the depth error
+    fn context(&self, err: ParseError, context: &str) -> ParseError {
+        if self.depth_exceeded {
+            err
+        } else {
+            err.context(context)
+        }
+    }
+
+    /// Number of errors recovered from so far
+    fn recovered_count(&self) -> usize {
+        self.errors.as_ref().map_or(0, Vec::len)
+    }
+
+    /// Forget the errors recovered from inside a speculative parse that is given up, keeping the
+    /// first `count`
+    fn discard_recovered(&mut self, count: usize) {
+        if let Some(errors) = &mut self.errors {
+            errors.truncate(count);
+        }
+    }
+
+    /// Consume a "(" and return its line, so that a missing ")" can point back to it
+    fn open_parenthesis(&mut self, reason: &str) -> Result<usize, ParseError> {
+        let line = self.current_line_number().unwrap_or_default();
+        self.check_and_eat_token(&C1Token::LeftParenthesis, reason)
+            .map(|_| line)
+    }
+
+    /// Consume the ")" that closes a "(" opened at line `opened_at`
+    fn close_parenthesis(&mut self, opened_at: usize) -> ParseResult {
+        self.check_and_eat_token_with(&C1Token::RightParenthesis, || {
+            format!(
+                r#"Expected "{}", unclosed '{}' opened at line {}"#,
+                C1Token::RightParenthesis,
+                C1Token::LeftParenthesis,
+                opened_at
+            )
+        })
+    }
+
+    /// Check whether the current token is equal to the given token. If yes, consume it, otherwise
+    /// return an error with the given error message
+    fn check_and_eat_token(&mut self, token: &C1Token, reason: &str) -> ParseResult {
+        self.check_and_eat_token_with(token, || reason.to_string())
+    }
+
+    /// Like `check_and_eat_token`, but the error message is only formatted if the token does not
+    /// match
+    fn check_and_eat_token_with(
+        &mut self,
+        token: &C1Token,
+        reason: impl FnOnce() -> String,
+    ) -> ParseResult {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(ParseError {
+                expected: vec![*token],
+                ..self.unexpected_token(&reason())
+            })
+        }
+    }
+
+    /// Build the error for a current token that does not match what `reason` expects
+    fn unexpected_token(&self, reason: &str) -> ParseError {
+        let message = match self.current_token() {
+            None => format!("{}. Reached EOF", reason),
+            Some(C1Token::Error) => {
+                let (line, text) = self.last_error().unwrap_or_default();
+                format!(
+                    "Unexpected token: {} \n at line {}, column {} while trying to parse: '{}', \
+                     the input '{}' is not a valid token",
+                    reason,
+                    line,
+                    self.current_column().unwrap(),
+                    text,
+                    text
+                )
+            }
+            Some(_) => format!(
+                "Unexpected token: {} \n at line {}, column {} while trying to parse: '{}'",
+                reason,
+                self.current_line_number().unwrap(),
+                self.current_column().unwrap(),
+                self.current_text().unwrap()
+            ),
+        };
+        self.error(message)
+    }
+
+    /// Build an error located at the current token, or after the last token at EOF
+    fn error(&self, message: String) -> ParseError {
+        self.errors_built.set(self.errors_built.get() + 1);
+        ParseError {
+            message,
+            line: self
+                .current_line_number()
+                .or_else(|| self.previous_line_number())
+                .unwrap_or(1),
+            column: self.current_column(),
+            expected: vec![],
+            found: self.current_token(),
+        }
+    }
+
+    /// Build an error located at the token that was consumed last, like an invalid literal
+    fn error_at_previous(&self, message: String) -> ParseError {
+        ParseError {
+            message,
+            line: self.previous_line_number().unwrap_or(1),
+            column: self.previous_column(),
+            expected: vec![],
+            found: self.previous_token(),
+        }
+    }
+
+    /// Check whether the given token matches the current token
+    fn current_matches(&self, token: &C1Token) -> bool {
+        match &self.current_token() {
+            None => false,
+            Some(current) => current == token,
+        }
+    }
+
+    /// Consume the current token if it is equal to the given one. Unlike `check_and_eat_token`,
+    /// no error is built, which makes it cheap for tokens that are only optional.
+    fn eat(&mut self, token: &C1Token) -> bool {
+        let matches = self.current_matches(token);
+        if matches {
+            self.advance();
+        }
+        matches
+    }
+
+    /// Consume and return the current token if it is any of the given ones, without building an
+    /// error otherwise
+    fn eat_any(&mut self, tokens: &[C1Token]) -> Option<C1Token> {
+        let token = self
+            .current_token()
+            .filter(|token| tokens.contains(token))?;
+        self.advance();
+        Some(token)
+    }
+
+    /// Consume and return the current token if it is any of the given ones. The error names all
+    /// of the tokens, after `reason` if it is not empty.
+    fn any_match_and_eat(
+        &mut self,
+        token: &[C1Token],
+        reason: &str,
+    ) -> Result<C1Token, ParseError> {
+        if let Some(matched) = self.eat_any(token) {
+            Ok(matched)
+        } else {
+            let names: Vec<String> = token.iter().map(C1Token::to_string).collect();
+            let expected = format!("expected one of {}", names.join(", "));
+            let reason = match reason {
+                "" => expected,
+                _ => format!("{}, {}", reason, expected),
+            };
+            Err(ParseError {
+                expected: token.to_vec(),
+                ..self.unexpected_token(&reason)
+            })
+        }
+    }
+}
+
+/// Combine two operands with the binary operator of `token`
+fn binary(token: C1Token, left: Expr, right: Expr) -> Expr {
+    let op = match token {
+        C1Token::Plus => BinaryOp::Add,
+        C1Token::Minus => BinaryOp::Sub,
+        C1Token::Asterisk => BinaryOp::Mul,
+        C1Token::Slash => BinaryOp::Div,
+        C1Token::Percent => BinaryOp::Rem,
+        C1Token::Equal => BinaryOp::Equal,
+        C1Token::NotEqual => BinaryOp::NotEqual,
+        C1Token::Less => BinaryOp::Less,
+        C1Token::Greater => BinaryOp::Greater,
+        C1Token::LessEqual => BinaryOp::LessEqual,
+        C1Token::GreaterEqual => BinaryOp::GreaterEqual,
+        C1Token::And => BinaryOp::And,
+        C1Token::Or => BinaryOp::Or,
+        C1Token::BitAnd => BinaryOp::BitAnd,
+        C1Token::BitOr => BinaryOp::BitOr,
+        C1Token::BitXor => BinaryOp::BitXor,
+        C1Token::ShiftLeft => BinaryOp::ShiftLeft,
+        C1Token::ShiftRight => BinaryOp::ShiftRight,
+        _ => unreachable!("{:?} is not a binary operator", token),
+    };
+    let span = Span {
+        start: left.span.start,
+        end: right.span.end,
+    };
+    let kind = ExprKind::Binary {
+        op,
+        left: Box::new(left),
+        right: Box::new(right),
+    };
+    Expr { kind, span }
+}
+
+/// The level of a binary operator token, see `binary_expr`
+fn binary_level(token: C1Token) -> Option<u8> {
+    let level = match token {
+        C1Token::Or => LOGOR,
+        C1Token::And => LOGAND,
+        C1Token::BitOr => 2,
+        C1Token::BitXor => 3,
+        C1Token::BitAnd => BITAND,
+        C1Token::Equal
+        | C1Token::NotEqual
+        | C1Token::LessEqual
+        | C1Token::GreaterEqual
+        | C1Token::Less
+        | C1Token::Greater => COMPARISON,
+        C1Token::ShiftLeft | C1Token::ShiftRight => 6,
+        C1Token::Plus | C1Token::Minus => SIMPEXPR,
+        C1Token::Asterisk | C1Token::Slash | C1Token::Percent => TERM,
+        _ => return None,
+    };
+    Some(level)
+}
+
+/// The error for a file or directory at `path` that cannot be read, located at line 1
+fn io_error(path: &Path, err: io::Error) -> ParseError {
+    ParseError {
+        message: format!("{}: {}", path.display(), err),
+        line: 1,
+        column: None,
+        expected: vec![],
+        found: None,
+    }
+}
+
+/// The 1-based start and end (line, column) of the token `err` was found at
+fn error_range(text: &str, err: &ParseError) -> ((usize, usize), (usize, usize)) {
+    let token = err.column.and_then(|column| {
+        C1Lexer::new(text)
+            .tokens()
+            .find(|token| token.line_number() == err.line && token.column() == column)
+    });
+    match token {
+        Some(token) => {
+            let span = token.span();
+            let end = Span {
+                start: span.end,
+                end: span.end,
+            };
+            ((err.line, token.column()), end.line_column(text))
+        }
+        // at the end of the input, or an error not located at a token
+        None => {
+            let line = text
+                .lines()
+                .nth(err.line.saturating_sub(1))
+                .unwrap_or_default();
+            let column = err.column.unwrap_or(line.chars().count() + 1);
+            ((err.line, column), (err.line, column))
+        }
+    }
+}
+
+/// An LSP `Diagnostic` object for 1-based (line, column) positions, which LSP counts from 0
+fn lsp_diagnostic(
+    start: (usize, usize),
+    end: (usize, usize),
+    severity: u8,
+    message: &str,
+) -> String {
+    let position = |(line, column): (usize, usize)| {
+        format!(
+            r#"{{"line":{},"character":{}}}"#,
+            line.saturating_sub(1),
+            column.saturating_sub(1)
+        )
+    };
+    format!(
+        r#"{{"range":{{"start":{},"end":{}}},"severity":{},"source":"cb-3","message":"{}"}}"#,
+        position(start),
+        position(end),
+        severity,
+        json::escape(message)
+    )
+}
+
+/// Whether `expr` has no subexpressions, like a literal or a variable
+fn is_leaf(expr: &Expr) -> bool {
+    matches!(
+        expr.kind,
+        ExprKind::Int(_)
+            | ExprKind::Float(_)
+            | ExprKind::Bool(_)
+            | ExprKind::Char(_)
+            | ExprKind::String(_)
+            | ExprKind::Variable(_)
+    )
+}
+
+/// Value of a ConstInt in decimal, hexadecimal (`0x`) or binary (`0b`) notation
+fn int_literal(text: &str) -> Result<i64, String> {
+    let digits = text.replace('_', "");
+    let value = match digits.get(..2) {
+        Some("0x" | "0X") => i64::from_str_radix(&digits[2..], 16),
+        Some("0b" | "0B") => i64::from_str_radix(&digits[2..], 2),
+        _ => digits.parse(),
+    };
+    value.map_err(|_| format!("Integer literal '{}' is out of range", text))
+}
+
+/// Value of a ConstFloat; `inf` and `nan` are accepted as well, see `new_with_float_specials`
+fn float_literal(text: &str) -> Result<f64, String> {
+    text.replace('_', "")
+        .parse()
+        .map_err(|_| format!("Invalid float literal '{}'", text))
+}
+
+/// Value of a ConstChar like `'a'` or `'\n'`; the lexer only accepts valid escapes
+fn char_literal(text: &str) -> char {
+    let mut chars = text[1..text.len() - 1].chars();
+    match (chars.next(), chars.next()) {
+        (Some('\\'), Some('n')) => '\n',
+        (Some('\\'), Some('t')) => '\t',
+        (Some('\\'), Some(escaped)) => escaped,
+        (Some(c), _) => c,
+        (None, _) => unreachable!("empty char literal"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{
+        AssignOp, BinaryOp, Declaration, Expr, ExprKind, Function, IncDecOp, Item, Parameter,
+        Program, Signature, Span, Stmt, StmtKind, Type, UnaryOp, Variable,
+    };
+    use crate::cst::{CstKind, CstNode};
+    use crate::parser::{C1Parser, ParseResult, COMPARISON, LOGAND, LOGOR, SIMPEXPR, TERM};
+    use crate::{C1Token, ParseError};
+
+    fn call_method<'a, T, F>(parse_method: F, text: &'static str) -> ParseResult
+    where
+        F: Fn(&mut C1Parser<'a>) -> Result<T, ParseError>,
+    {
+        let mut parser = C1Parser::initialize_parser(text);
+        if let Err(message) = parse_method(&mut parser) {
+            eprintln!("Parse Error: {}", message);
+            Err(message)
+        } else {
+            println!("{:?}", parser.current_token());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn parse_empty_program() {
+        let result = C1Parser::parse("");
+        assert_eq!(result, Ok(()));
+
+        let result = C1Parser::parse("   ");
+        assert_eq!(result, Ok(()));
+
+        let result = C1Parser::parse("// This is a valid comment!");
+        assert_eq!(result, Ok(()));
+
+        let result = C1Parser::parse("/* This is a valid comment!\nIn two lines!*/\n");
+        assert_eq!(result, Ok(()));
+
+        let result = C1Parser::parse("  \n ");
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn fail_invalid_program() {
+        let result = C1Parser::parse("  bool  ");
+        println!("{:?}", result);
+        assert!(result.is_err());
+
+        let result = C1Parser::parse("x = 0;");
+        println!("{:?}", result);
+        assert!(result.is_err());
+
+        let result = C1Parser::parse("// A valid comment\nInvalid line.");
+        println!("{:?}", result);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn valid_function() {
+        // let result = C1Parser::parse("  void foo() {}  ");
+        // assert!(result.is_ok(), "Parse result: {}", result.err().unwrap());
+
+        let result = C1Parser::parse("int bar() {return 0;}");
+        assert!(result.is_ok(), "Parse result: {}", result.err().unwrap());
+
+        let result = C1Parser::parse(
+            "float calc() {\n\
+        x = 1.0;
+        y = 2.2;
+        return x + y;
+        \n\
+        }",
+        );
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn fail_invalid_function() {
+        let result = C1Parser::parse("  void foo()) {}  ");
+        println!("{:?}", result);
+        assert!(result.is_err());
+
+        let result = C1Parser::parse("const bar() {return 0;}");
+        println!("{:?}", result);
+        assert!(result.is_err());
+
+        let result = C1Parser::parse(
+            "int bar() {
+                                                          return 0;
+                                                     int foo() {}",
+        );
+        println!("{:?}", result);
+        assert!(result.is_err());
+
+        let result = C1Parser::parse(
+            "float calc(int) {\n\
+        int x = 1.0;
+        int y = 2.2;
+        return x + y;
+        \n\
+        }",
+        );
+        println!("{:?}", result);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn fail_const_function() {
+        let result = C1Parser::parse("const bar() {return 0;}");
+        assert!(result.unwrap_err().message.contains("Expected type"));
+    }
+
+    #[test]
+    fn void_parameterlist() {
+        assert!(C1Parser::parse("int f(void){}").is_ok());
+        assert_eq!(
+            C1Parser::parse("int f(void){}"),
+            C1Parser::parse("int f(){}")
+        );
+        assert!(C1Parser::parse("int f(void, int a){}").is_err());
+        assert!(C1Parser::parse("int f(void void){}").is_err());
+    }
+
+    #[test]
+    fn valid_parameterlist() {
+        assert!(C1Parser::parse("int f(int a) {}").is_ok());
+        assert!(C1Parser::parse("void g(float x, bool y) {}").is_ok());
+        assert!(C1Parser::parse("int h() {}").is_ok());
+        assert!(call_method(C1Parser::parameterlist, "int a, int b, float c").is_ok());
+    }
+
+    #[test]
+    fn fail_invalid_parameterlist() {
+        let err = C1Parser::parse("int f(int a,) {}").unwrap_err();
+        assert!(err.message.contains("Expected type"), "{}", err);
+        assert!(C1Parser::parse("int f(int) {}").is_err());
+        assert!(C1Parser::parse("int f(a) {}").is_err());
+        assert!(C1Parser::parse("int f(int a int b) {}").is_err());
+    }
+
+    #[test]
+    fn valid_functioncall() {
+        assert!(call_method(C1Parser::functioncall, "foo()").is_ok());
+        assert!(call_method(C1Parser::functioncall, "foo( )").is_ok());
+        assert!(call_method(C1Parser::functioncall, "bar23( )").is_ok());
+        assert!(call_method(C1Parser::functioncall, "foo(1)").is_ok());
+        assert!(call_method(C1Parser::functioncall, "foo(a + 1, bar(b), c = 2)").is_ok());
+    }
+
+    #[test]
+    fn fail_invalid_functioncall() {
+        assert!(call_method(C1Parser::functioncall, "foo)").is_err());
+        assert!(call_method(C1Parser::functioncall, "foo{ )").is_err());
+        assert!(call_method(C1Parser::functioncall, "bar _foo( )").is_err());
+        assert!(call_method(C1Parser::functioncall, "foo(1,)").is_err());
+        assert!(call_method(C1Parser::functioncall, "foo(,1)").is_err());
+    }
+
+    #[test]
+    fn missing_parenthesis_error() {
+        let err = call_method(C1Parser::functioncall, "foo(1\n  ;").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert_eq!(err.column, Some(3));
+        assert_eq!(err.expected, vec![C1Token::RightParenthesis]);
+        assert_eq!(err.found, Some(C1Token::Semicolon));
+        assert_eq!(
+            err.to_string(),
+            "Unexpected token: Expected \")\", unclosed '(' opened at line 1 \n at line 2, \
+             column 3 while trying to parse: ';', in functioncall"
+        );
+
+        // at the end of the input the error points at the last token
+        let err = call_method(C1Parser::functioncall, "foo(1").unwrap_err();
+        assert_eq!((err.line, err.column, err.found), (1, None, None));
+        assert_eq!(err.expected, vec![C1Token::RightParenthesis]);
     }
 
     #[test]
     fn valid_statementlist() {
         assert!(call_method(C1Parser::statementlist, "return 3;").is_ok());
-        assert!(call_method(
-            C1Parser::statementlist,
-            "x = 4;\ny = 2.1;"
-        )
-        .is_ok());
+        assert!(call_method(C1Parser::statementlist, "x = 4;\ny = 2.1;").is_ok());
         assert!(call_method(
             C1Parser::statementlist,
             "x = 4;\n\
@@ -463,20 +1820,347 @@ mod tests {
     }
 
     #[test]
-    fn valid_ifstatement() {
-        assert!(call_method(C1Parser::ifstatement, "if(x == y) {}").is_ok());
-        assert!(call_method(C1Parser::ifstatement, "if(z) {}").is_ok());
-        assert!(call_method(C1Parser::ifstatement, "if(true) {}").is_ok());
-        assert!(call_method(C1Parser::ifstatement, "if(false) {}").is_ok());
+    fn fail_invalid_assignment_target() {
+        let err = call_method(C1Parser::statement, "5 = x;").unwrap_err();
+        assert_eq!(
+            err.message,
+            "Unexpected token: invalid assignment target \n at line 1, column 1 while trying to \
+             parse: '5'"
+        );
+        assert_eq!(err.found, Some(C1Token::ConstInt));
+
+        let result = call_method(C1Parser::statement, "foo() = 1;");
+        assert!(result
+            .unwrap_err()
+            .message
+            .contains("invalid assignment target"));
+
+        let result = call_method(C1Parser::statement, "x = = 1;");
+        assert!(!result
+            .unwrap_err()
+            .message
+            .contains("invalid assignment target"));
+
+        for text in ["-x = 1;", "!x = 1;"] {
+            let err = call_method(C1Parser::statement, text).unwrap_err();
+            assert!(err.message.contains("invalid assignment target"), "{}", err);
+        }
+
+        let result = call_method(C1Parser::statement, "(a) = 1;");
+        assert!(result
+            .unwrap_err()
+            .message
+            .contains("invalid assignment target"));
+
+        assert!(call_method(C1Parser::statement, "x = 1;").is_ok());
+    }
+
+    #[test]
+    fn valid_incdecstatement() {
+        assert!(call_method(C1Parser::statement, "i++;").is_ok());
+        assert!(call_method(C1Parser::statement, "i--;").is_ok());
+        assert!(call_method(C1Parser::statement, "++i;").is_ok());
+        assert!(call_method(C1Parser::statement, "--i;").is_ok());
+        assert!(call_method(C1Parser::statement, "i++").is_err());
+        assert!(call_method(C1Parser::statement, "5++;").is_err());
+        assert!(call_method(C1Parser::statement, "x = i++;").is_err());
+    }
+
+    #[test]
+    fn valid_ifstatement() {
+        assert!(call_method(C1Parser::ifstatement, "if(x == y) {}").is_ok());
+        assert!(call_method(C1Parser::ifstatement, "if(z) {}").is_ok());
+        assert!(call_method(C1Parser::ifstatement, "if(true) {}").is_ok());
+        assert!(call_method(C1Parser::ifstatement, "if(false) {}").is_ok());
+    }
+
+    #[test]
+    fn valid_declaration() {
+        assert!(call_method(C1Parser::declaration, "int x;").is_ok());
+        assert!(call_method(C1Parser::declaration, "float y = 1.0;").is_ok());
+        assert!(call_method(C1Parser::declaration, "bool b = a > c;").is_ok());
+
+        let mut parser = C1Parser::initialize_parser("int x = 1; x = 2;");
+        assert!(parser.statement().is_ok());
+        assert_eq!(parser.current_text(), Some("x"));
+
+        let result = C1Parser::parse(
+            "float calc(int valid) {\n\
+        int x = 1.0;
+        int y = 2.2;
+        return x + y;
+        \n\
+        }",
+        );
+        assert!(result.is_ok(), "Parse result: {}", result.err().unwrap());
+    }
+
+    #[test]
+    fn fail_invalid_declaration() {
+        assert!(call_method(C1Parser::declaration, "int x").is_err());
+        assert!(call_method(C1Parser::declaration, "int = 1;").is_err());
+        assert!(call_method(C1Parser::declaration, "int x = ;").is_err());
+        assert!(call_method(C1Parser::declaration, "x = 1;").is_err());
+        assert!(C1Parser::parse("void f() { int 1; }").is_err());
+    }
+
+    #[test]
+    fn multiple_declarations() {
+        assert!(call_method(C1Parser::declaration, "int a, b;").is_ok());
+        assert!(call_method(C1Parser::declaration, "float x = 1.0, y;").is_ok());
+        assert!(call_method(C1Parser::declaration, "int a = 1, b = 2, c = a + b;").is_ok());
+        assert!(C1Parser::parse("int a, b; void f() { bool p, q = true; }").is_ok());
+
+        let err = call_method(C1Parser::declaration, "int a,;").unwrap_err();
+        assert!(err.message.contains("Expected variable name"), "{}", err);
+        assert!(call_method(C1Parser::declaration, "int a b;").is_err());
+        assert!(call_method(C1Parser::declaration, "int a, float b;").is_err());
+    }
+
+    #[test]
+    fn arrays() {
+        assert!(call_method(C1Parser::declaration, "int a[3];").is_ok());
+        assert!(call_method(C1Parser::declaration, "float v[2], w[4], n;").is_ok());
+        assert!(call_method(C1Parser::statement, "x = a[i+1];").is_ok());
+        assert!(call_method(C1Parser::statement, "x = a[b[0]] * 2;").is_ok());
+        assert!(C1Parser::parse("int a[10]; int f() { return a[0]; }").is_ok());
+        assert!(call_method(C1Parser::statement, "a[0] = 1;").is_ok());
+        assert!(call_method(C1Parser::statement, "a[i + 1] += a[i];").is_ok());
+        assert!(call_method(C1Parser::forstatement, "for (;i < 3; a[i] = 0) i++;").is_ok());
+
+        let statement = C1Parser::initialize_parser("a[i] = 1;")
+            .statement()
+            .unwrap();
+        let StmtKind::Assign {
+            target,
+            index: Some(index),
+            ..
+        } = statement.kind
+        else {
+            panic!("{:?}", statement)
+        };
+        assert_eq!(target, "a");
+        assert_eq!(index.kind, ExprKind::Variable("i".to_string()));
+
+        assert!(call_method(C1Parser::statement, "x = a[];").is_err());
+        assert!(call_method(C1Parser::statement, "x = a[1;").is_err());
+        assert!(call_method(C1Parser::statement, "a[] = 1;").is_err());
+        // a missing value is not blamed on the element as the target
+        let err = call_method(C1Parser::statement, "a[0] = ;").unwrap_err();
+        assert!(
+            !err.message.contains("invalid assignment target"),
+            "{}",
+            err
+        );
+        assert!(call_method(C1Parser::declaration, "int a[];").is_err());
+        assert!(call_method(C1Parser::declaration, "int a[n];").is_err());
+    }
+
+    #[test]
+    fn global_declarations() {
+        let result = C1Parser::parse(
+            "int counter = 0;\n\
+             float scale;\n\
+             void tick() { counter = counter + 1; }\n\
+             bool done = counter > 10;\n\
+             int main() { tick(); return counter; }",
+        );
+        assert!(result.is_ok(), "Parse result: {}", result.err().unwrap());
+        assert!(C1Parser::parse("int counter = 0;").is_ok());
+
+        let largest = C1Parser::largest_function("int x; void f() { a = 1; b = 2; }");
+        assert_eq!(largest, Ok(Some(("f".to_string(), 2))));
+
+        assert!(C1Parser::parse("int counter = 0").is_err());
+        assert!(C1Parser::parse("int counter = ;").is_err());
+        assert!(C1Parser::parse("counter = 0;").is_err());
+    }
+
+    #[test]
+    fn valid_else() {
+        assert!(call_method(C1Parser::ifstatement, "if(x){}else{}").is_ok());
+        assert!(call_method(C1Parser::ifstatement, "if(x){}else if(y){}").is_ok());
+        assert!(call_method(C1Parser::ifstatement, "if(x){}else if(y){}else{ z = 1; }").is_ok());
+        assert!(call_method(C1Parser::ifstatement, "if(x) y = 1; else y = 2;").is_ok());
+
+        // the else binds to the inner if, leaving nothing behind
+        let mut parser = C1Parser::initialize_parser("if(a) if(b) x = 1; else x = 2; y = 3;");
+        assert!(parser.ifstatement().is_ok());
+        assert_eq!(parser.current_text(), Some("y"));
+
+        let mut parser = C1Parser::initialize_parser("if(x){} y=1;");
+        assert!(parser.ifstatement().is_ok());
+        assert_eq!(parser.current_text(), Some("y"));
+        assert!(parser.statement().is_ok());
+        assert_eq!(parser.current_token(), None);
+
+        let result = C1Parser::parse("void f() { if(x){} else {} y = 1; }");
+        assert!(result.is_ok(), "Parse result: {}", result.err().unwrap());
+    }
+
+    #[test]
+    fn fail_invalid_else() {
+        assert!(C1Parser::parse("void f() { if(x){} else }").is_err());
+        assert!(C1Parser::parse("void f() { else {} }").is_err());
+        assert!(C1Parser::parse("void f() { if(x){} else {} else {} }").is_err());
+    }
+
+    #[test]
+    fn fail_invalid_ifstatement() {
+        assert!(call_method(C1Parser::ifstatement, "if(x == ) {}").is_err());
+        assert!(call_method(C1Parser::ifstatement, "if( == y) {}").is_err());
+        assert!(call_method(C1Parser::ifstatement, "if(> z) {}").is_err());
+        assert!(call_method(C1Parser::ifstatement, "if( {}").is_err());
+        assert!(call_method(C1Parser::ifstatement, "if(false) }").is_err());
+    }
+
+    #[test]
+    fn valid_whilestatement() {
+        assert!(call_method(C1Parser::whilestatement, "while(x < 10) { x = x + 1; }").is_ok());
+        assert!(call_method(C1Parser::whilestatement, "while(true) {}").is_ok());
+        assert!(call_method(C1Parser::statement, "while(x) x = x - 1;").is_ok());
+
+        let result = C1Parser::parse("void f() { while(x < 10) { x = x + 1; } return; }");
+        assert!(result.is_ok(), "Parse result: {}", result.err().unwrap());
+    }
+
+    #[test]
+    fn fail_invalid_whilestatement() {
+        assert!(call_method(C1Parser::whilestatement, "while() {}").is_err());
+        assert!(call_method(C1Parser::whilestatement, "while(x {}").is_err());
+        assert!(call_method(C1Parser::whilestatement, "while x {}").is_err());
+    }
+
+    #[test]
+    fn valid_dowhilestatement() {
+        let text = "do { x = x + 1; } while(x < 10);";
+        assert!(call_method(C1Parser::dowhilestatement, text).is_ok());
+        assert!(call_method(C1Parser::statement, "do x = 1; while(false);").is_ok());
+
+        let result = C1Parser::parse("void f() { do { x = x + 1; } while(x < 10); }");
+        assert!(result.is_ok(), "Parse result: {}", result.err().unwrap());
+    }
+
+    #[test]
+    fn fail_invalid_dowhilestatement() {
+        let result = call_method(C1Parser::dowhilestatement, "do {} while(x)\ny = 1;");
+        let err = result.unwrap_err();
+        assert!(
+            err.message
+                .contains("Expected semicolon after do-while loop"),
+            "{}",
+            err
+        );
+        assert!(err.message.contains("at line 2"), "{}", err);
+
+        assert!(call_method(C1Parser::dowhilestatement, "do {} while(x)").is_err());
+        assert!(call_method(C1Parser::dowhilestatement, "do {};").is_err());
+        assert!(call_method(C1Parser::dowhilestatement, "do {} while();").is_err());
+        assert!(C1Parser::parse("void f() { do {} while(x) }").is_err());
+    }
+
+    #[test]
+    fn valid_forstatement() {
+        let text = "for(i = 0; i < 10; i = i + 1) {}";
+        assert!(call_method(C1Parser::forstatement, text).is_ok());
+        assert!(call_method(C1Parser::forstatement, "for(; x; ) {}").is_ok());
+        assert!(call_method(C1Parser::statement, "for(i = 0; i < n; i += 1) x = x * 2;").is_ok());
+
+        let result = C1Parser::parse("int f() { for(i = 0; i < 10; i = i + 1) { x = i; } }");
+        assert!(result.is_ok(), "Parse result: {}", result.err().unwrap());
+
+        // the init may declare the loop variable instead
+        let text = "for(int i = 0, j; i < 10; i = i + 1) {}";
+        assert!(call_method(C1Parser::forstatement, text).is_ok());
+        let mut parser = C1Parser::initialize_parser("for (int i = 0; i < n; i += 1) {}");
+        let Stmt {
+            kind: StmtKind::For { init, .. },
+            ..
+        } = parser
+            .forstatement()
+            .unwrap_or_else(|err| panic!("{}", err))
+        else {
+            unreachable!()
+        };
+        let StmtKind::Declaration(declaration) = &init.as_ref().unwrap().kind else {
+            panic!("{:?}", init)
+        };
+        assert_eq!(declaration.ty, Type::Int);
+        assert_eq!(declaration.variables[0].name, "i");
+        assert_eq!(init.unwrap().span, Span { start: 5, end: 15 });
+    }
+
+    #[test]
+    fn fail_invalid_forstatement() {
+        let result = call_method(C1Parser::forstatement, "for(i=0 i<10; i=i+1){}");
+        let err = result.unwrap_err();
+        assert!(
+            err.message.contains("Expected semicolon after for init"),
+            "{}",
+            err
+        );
+
+        assert!(call_method(C1Parser::forstatement, "for(;;) {}").is_err());
+        assert!(call_method(C1Parser::forstatement, "for(x) {}").is_err());
+        assert!(call_method(C1Parser::forstatement, "for(int i = 0;; i = 1) {}").is_err());
+        assert!(call_method(C1Parser::forstatement, "for(int i = 0 i < 1;) {}").is_err());
+        assert!(call_method(C1Parser::forstatement, "for(; x) {}").is_err());
+        assert!(call_method(C1Parser::forstatement, "for(; x; ) ").is_err());
+    }
+
+    #[test]
+    fn unclosed_parenthesis_reports_opening_line() {
+        let result = call_method(C1Parser::ifstatement, "if(x\n{ }");
+        let err = result.unwrap_err();
+        assert!(
+            err.message.contains("unclosed '(' opened at line 1"),
+            "{}",
+            err
+        );
+        assert!(err.message.contains("at line 2"), "{}", err);
+
+        let result = call_method(C1Parser::factor, "(\n(a +\nb)");
+        let err = result.unwrap_err();
+        assert!(
+            err.message.contains("unclosed '(' opened at line 1"),
+            "{}",
+            err
+        );
+        assert!(err.message.contains("Reached EOF"), "{}", err);
+
+        let err = call_method(C1Parser::printf, "printf(\n\n x;").unwrap_err();
+        assert!(
+            err.message.contains("unclosed '(' opened at line 1"),
+            "{}",
+            err
+        );
     }
 
     #[test]
-    fn fail_invalid_ifstatement() {
-        assert!(call_method(C1Parser::ifstatement, "if(x == ) {}").is_err());
-        assert!(call_method(C1Parser::ifstatement, "if( == y) {}").is_err());
-        assert!(call_method(C1Parser::ifstatement, "if(> z) {}").is_err());
-        assert!(call_method(C1Parser::ifstatement, "if( {}").is_err());
-        assert!(call_method(C1Parser::ifstatement, "if(false) }").is_err());
+    fn empty_statement() {
+        assert!(call_method(C1Parser::statement, ";").is_ok());
+        assert!(call_method(C1Parser::statementlist, ";;").is_ok());
+        assert!(call_method(C1Parser::whilestatement, "while(x);").is_ok());
+        assert!(call_method(C1Parser::forstatement, "for(; x; );").is_ok());
+
+        let result = C1Parser::parse("void f() { ;;; x = 1;; }");
+        assert!(result.is_ok(), "Parse result: {}", result.err().unwrap());
+
+        let mut parser = C1Parser::initialize_parser(";;;;");
+        assert!(parser.statementlist().is_ok());
+        assert_eq!(parser.current_token(), None);
+    }
+
+    #[test]
+    fn break_and_continue() {
+        assert!(call_method(C1Parser::statement, "break;").is_ok());
+        assert!(call_method(C1Parser::statement, "continue;").is_ok());
+        let result = C1Parser::parse("void f() { while(true) { if(x) break; continue; } }");
+        assert!(result.is_ok(), "Parse result: {}", result.err().unwrap());
+
+        assert!(C1Parser::parse("void f() { break }").is_err());
+        assert!(C1Parser::parse("void f() { continue }").is_err());
+        assert!(call_method(C1Parser::statement, "break = 1;").is_err());
     }
 
     #[test]
@@ -489,6 +2173,16 @@ mod tests {
     #[test]
     fn fail_invalid_returnstatement() {
         assert!(call_method(C1Parser::returnstatement, "1").is_err());
+        // a missing semicolon is reported as such, not as a missing value
+        for text in ["void f() { return }", "void f() { return"] {
+            let err = C1Parser::parse(text).unwrap_err();
+            assert!(
+                err.message
+                    .contains("Expected semicolon after return statement"),
+                "{}",
+                err
+            );
+        }
     }
 
     #[test]
@@ -496,6 +2190,11 @@ mod tests {
         assert!(call_method(C1Parser::printf, " printf(a+b)").is_ok());
         assert!(call_method(C1Parser::printf, "printf( 1)").is_ok());
         assert!(call_method(C1Parser::printf, "printf(a - c)").is_ok());
+        assert!(call_method(C1Parser::printf, r#"printf("hi")"#).is_ok());
+        assert!(call_method(C1Parser::statement, r#"x = "msg";"#).is_ok());
+        assert!(call_method(C1Parser::printf, r#"printf("%d", x)"#).is_ok());
+        assert!(call_method(C1Parser::printf, r#"printf("%d %d\n", a, b)"#).is_ok());
+        assert!(call_method(C1Parser::printf, r#"printf("%d", a + b, f())"#).is_ok());
     }
 
     #[test]
@@ -503,6 +2202,518 @@ mod tests {
         assert!(call_method(C1Parser::printf, "printf( ").is_err());
         assert!(call_method(C1Parser::printf, "printf(printf)").is_err());
         assert!(call_method(C1Parser::printf, "Printf()").is_err());
+        assert!(call_method(C1Parser::printf, r#"printf("%d", )"#).is_err());
+        assert!(call_method(C1Parser::printf, r#"printf(, x)"#).is_err());
+    }
+
+    #[test]
+    fn asm_statement() {
+        let result = C1Parser::parse_with_asm(r#"void f() { asm("nop"); x = 1; }"#);
+        assert!(result.is_ok(), "Parse result: {}", result.err().unwrap());
+
+        assert!(C1Parser::parse(r#"void f() { asm("nop"); }"#).is_err());
+        assert!(C1Parser::parse_with_asm("void f() { asm(1); }").is_err());
+        assert!(C1Parser::parse_with_asm(r#"void f() { asm("nop") }"#).is_err());
+    }
+
+    /// The kind and text of every top-level item of a CST
+    fn items(cst: &CstNode) -> Vec<(CstKind, String)> {
+        let CstNode::Node {
+            kind: CstKind::Program,
+            children,
+        } = cst
+        else {
+            panic!("{:?}", cst)
+        };
+        children
+            .iter()
+            .filter_map(|child| match child {
+                CstNode::Node { kind, .. } => Some((*kind, child.text())),
+                CstNode::Token { .. } => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parse_cst() {
+        let text = "/* header */\nint f() {\n  // body\n  if (x) { return 1; }\r\n}\n\n\
+                    void g() {}  ";
+        let cst = C1Parser::parse_cst(text).unwrap();
+        assert_eq!(cst.text(), text);
+        let functions: Vec<_> = items(&cst)
+            .into_iter()
+            .filter(|(kind, _)| *kind == CstKind::FunctionDefinition)
+            .map(|(_, text)| text)
+            .collect();
+        assert_eq!(
+            functions,
+            vec![
+                "int f() {\n  // body\n  if (x) { return 1; }\r\n}",
+                "void g() {}"
+            ]
+        );
+
+        // a global is an item of its own, not part of the function after it
+        let text = "int x = 1; /* x */\nvoid f() { x = 2; }\nfloat a[2], b;";
+        let cst = C1Parser::parse_cst(text).unwrap();
+        assert_eq!(cst.text(), text);
+        assert_eq!(
+            items(&cst),
+            vec![
+                (CstKind::Declaration, "int x = 1;".to_string()),
+                (
+                    CstKind::FunctionDefinition,
+                    "void f() { x = 2; }".to_string()
+                ),
+                (CstKind::Declaration, "float a[2], b;".to_string()),
+            ]
+        );
+
+        assert!(C1Parser::parse_cst("int f() {").is_err());
+    }
+
+    #[test]
+    fn backtrack_log() {
+        // statements are told apart without backtracking
+        let (result, log) = C1Parser::parse_with_backtrack_log("void f() { g(); x = g(); }");
+        assert!(result.is_ok(), "Parse result: {}", result.err().unwrap());
+        assert!(log.is_empty(), "{:?}", log);
+
+        // an identifier followed by no assignment operator is taken back
+        let (result, log) = C1Parser::parse_with_backtrack_log("void f() { a < b; }");
+        assert!(result.is_err());
+        assert!(log.contains(&(6, 5)), "{:?}", log);
+        assert!(log.iter().all(|(from, to)| to < from), "{:?}", log);
+
+        // the "+" is given back once no operand follows it
+        let (result, log) = C1Parser::parse_with_backtrack_log("void f() { x = 1 + ; }");
+        assert!(result.is_err());
+        assert!(log.contains(&(9, 8)), "{:?}", log);
+
+        let (result, log) = C1Parser::parse_with_backtrack_log("");
+        assert_eq!(result, Ok(()));
+        assert!(log.is_empty());
+    }
+
+    #[test]
+    fn deep_parentheses() {
+        let text = format!(
+            "void f() {{ x = {}a + 1{}; }}",
+            "(".repeat(40),
+            ")".repeat(40)
+        );
+        let start = std::time::Instant::now();
+        let program = C1Parser::parse_to_ast(&text).unwrap();
+        assert!(start.elapsed().as_secs() < 1, "{:?}", start.elapsed());
+        let Item::Function(function) = &program.items[0] else {
+            panic!("{:?}", program)
+        };
+        let StmtKind::Assign { value, .. } = &function.body[0].kind else {
+            panic!("{:?}", function)
+        };
+        assert!(matches!(
+            value.kind,
+            ExprKind::Binary {
+                op: BinaryOp::Add,
+                ..
+            }
+        ));
+
+        let text = format!(
+            "void f() {{ x = {}a + 1{}; }}",
+            "(".repeat(40),
+            ")".repeat(39)
+        );
+        assert!(C1Parser::parse(&text).is_err());
+    }
+
+    #[test]
+    fn reset() {
+        let mut parser = C1Parser::initialize_parser("int x = (1 + 2);");
+        let first = parser.program().unwrap();
+        assert_eq!(first.items.len(), 1);
+
+        let text = "void f() {\n  g((1 + 2));\n}\nvoid g(int a) {}";
+        parser.reset(text);
+        assert!(parser.memo.is_empty());
+        let second = parser.program().unwrap();
+        assert_eq!(second.items.len(), 2);
+        assert_eq!(second, C1Parser::parse_to_ast(text).unwrap());
+
+        // a reset in the middle of a failed parse starts over from the first token as well
+        parser.reset("int = 1;");
+        assert!(parser.program().is_err());
+        parser.reset("bool b;");
+        assert!(parser.program().is_ok());
+        assert_eq!(
+            (parser.current_token(), parser.previous_text()),
+            (None, Some(";"))
+        );
+    }
+
+    #[test]
+    fn tokens_consumed() {
+        let mut parser = C1Parser::initialize_parser("int x ; /* comment */");
+        assert_eq!(parser.tokens_consumed(), 0);
+        assert!(parser.program().is_ok());
+        assert_eq!(parser.tokens_consumed(), 3);
+
+        // speculatively read tokens are given back on failure
+        parser.reset("x = 1 +");
+        assert!(parser.statement().is_err());
+        assert_eq!(parser.tokens_consumed(), 0);
+        assert!(parser.assignment().is_ok());
+        assert_eq!(parser.tokens_consumed(), 3);
+    }
+
+    #[test]
+    fn very_long_sum() {
+        // results are not copied out of the memo, that would recurse once per term
+        let text = format!("void f() {{ x = a{}; }}", " + a".repeat(10_000));
+        assert_eq!(C1Parser::parse(&text), Ok(()));
+    }
+
+    #[test]
+    fn long_sum() {
+        let terms: Vec<String> = (0..1000).map(|i| format!("x{}", i)).collect();
+        let text = format!("void f() {{ y = {}; }}", terms.join(" + "));
+        let start = std::time::Instant::now();
+        let program = C1Parser::parse_to_ast(&text).unwrap();
+        assert!(start.elapsed().as_millis() < 500, "{:?}", start.elapsed());
+
+        let Item::Function(function) = &program.items[0] else {
+            panic!("{:?}", program)
+        };
+        let StmtKind::Assign { value, .. } = &function.body[0].kind else {
+            panic!("{:?}", function)
+        };
+        // grouped from the left, so the last term is the outermost right operand
+        let mut sums = 0;
+        let mut expr = value;
+        while let ExprKind::Binary {
+            op: BinaryOp::Add,
+            left,
+            right,
+        } = &expr.kind
+        {
+            if sums == 0 {
+                assert_eq!(right.kind, ExprKind::Variable("x999".to_string()));
+            }
+            sums += 1;
+            expr = left;
+        }
+        assert_eq!(sums, 999);
+        assert_eq!(expr.kind, ExprKind::Variable("x0".to_string()));
+    }
+
+    #[test]
+    fn max_depth() {
+        let err = C1Parser::parse(&format!("int x = {}1;", "(".repeat(10_000))).unwrap_err();
+        assert!(
+            err.message.starts_with("maximum nesting depth exceeded"),
+            "{}",
+            err
+        );
+        assert_eq!(err.column, Some(265));
+        assert!(C1Parser::parse(&format!("void f() {}", "{".repeat(10_000))).is_err());
+        assert!(C1Parser::parse(&format!("int x = {}1;", "- ".repeat(10_000))).is_err());
+        let text = format!(
+            "void f() {{ x = {}1{}; }}",
+            "(".repeat(10_000),
+            ")".repeat(10_000)
+        );
+        let err = C1Parser::parse(&text).unwrap_err();
+        assert!(
+            err.message.starts_with("maximum nesting depth exceeded"),
+            "{}",
+            err
+        );
+        let text = format!("void f() {{ {} x = 1; }}", "if (a) ".repeat(10_000));
+        assert!(C1Parser::parse(&text).is_err());
+
+        // anything up to the default depth fits the stack, also when the caller has little left
+        let nested = [
+            format!("int x = {}1{};", "(".repeat(255), ")".repeat(255)),
+            format!("int x = {}1{};", "f(".repeat(255), ")".repeat(255)),
+            format!("int x = {}1{};", "a[".repeat(255), "]".repeat(255)),
+            format!("void f() {{ {} x = 1; }}", "for (;a;) ".repeat(250)),
+        ];
+        let results = std::thread::Builder::new()
+            .stack_size(256 << 10)
+            .spawn(move || nested.map(|text| C1Parser::parse(&text)))
+            .unwrap()
+            .join()
+            .unwrap();
+        assert!(results.iter().all(Result::is_ok), "{:?}", results);
+        let text = format!("int x = {}1{};", "(".repeat(256), ")".repeat(256));
+        assert!(C1Parser::parse(&text).is_err());
+        let text = format!("int x = {}1{};", "(".repeat(50), ")".repeat(50));
+        assert!(C1Parser::parse_with_max_depth(&text, 20).is_err());
+        assert!(C1Parser::parse_with_max_depth("void f() { { x = (1); } }", 4).is_ok());
+        assert!(C1Parser::parse_with_max_depth("void f() { { x = ((1)); } }", 4).is_err());
+
+        // the error is not replaced by the one of an enclosing rule
+        let text = format!(
+            "void f() {{ x = {}1{}; }}",
+            "(".repeat(300),
+            ")".repeat(300)
+        );
+        let err = C1Parser::parse(&text).unwrap_err();
+        assert!(
+            err.message.starts_with("maximum nesting depth exceeded"),
+            "{}",
+            err
+        );
+        let errors = C1Parser::parse_all(&text).unwrap_err();
+        assert!(
+            errors[0]
+                .message
+                .starts_with("maximum nesting depth exceeded"),
+            "{:?}",
+            errors
+        );
+        // nor given back as a missing operand, or extended with the context of every rule
+        for op in ["+", "<"] {
+            let nested = format!("{}1{}", "(".repeat(300), ")".repeat(300));
+            let text = format!("void f() {{ x = 1 {} {}; }}", op, nested);
+            let err = C1Parser::parse(&text).unwrap_err();
+            assert_eq!(err.message, "maximum nesting depth exceeded");
+        }
+    }
+
+    #[test]
+    fn memoized_results() {
+        assert_eq!(parse_expr("((a + (b * c)))"), parse_expr("a + b * c"));
+        assert_eq!(parse_expr("x = (y = f((z)))"), parse_expr("x = y = f(z)"));
+
+        // a replayed result restores the cursor and the previous token as well
+        let mut parser = C1Parser::initialize_parser("(a + c) * b");
+        let m = parser.mark();
+        assert!(parser.factor().is_ok());
+        // only the leaves are stored, not the parenthesized sum around them
+        assert_eq!(parser.memo.len(), 2);
+        parser.undo(m);
+        parser.advance();
+        let m = parser.mark();
+        let first = parser.factor().unwrap();
+        parser.undo(m);
+        let second = parser.factor().unwrap();
+        assert_eq!(first, second);
+        assert_eq!(parser.memo.len(), 2);
+        assert_eq!(parser.current_text(), Some("+"));
+        assert_eq!(parser.previous_text(), Some("a"));
+        (0..3).for_each(|_| parser.advance());
+
+        // failures are replayed too
+        parser.advance();
+        assert!(parser.factor().is_ok());
+        let m = parser.mark();
+        assert!(parser.factor().is_err());
+        parser.undo(m);
+        assert!(parser.factor().is_err());
+        assert_eq!(parser.current_token(), None);
+    }
+
+    #[test]
+    fn no_errors_on_success() {
+        let mut parser = C1Parser::initialize_parser("x = a + b * (c - 1) < f(!d, e[2])");
+        assert!(parser.assignment().is_ok());
+        assert_eq!(parser.errors_built.get(), 0);
+
+        assert!(parser.assignment().is_err());
+        assert_eq!(parser.errors_built.get(), 1);
+
+        // statements are told apart by peeking, so none of them is tried and given up
+        let text = "
+            int n = 3;
+            float half(int x) { return x / 2.0; }
+            void main() {
+                int i;
+                float f[2];
+                for (i = 0; i < n; i += 1) {
+                    if (i == 1) { continue; } else n = f[i] < half(i) ? 1 : 2;
+                    i++;
+                    --i;
+                }
+                while (true) { break; }
+                do { printf(\"%d\", i); ; } while (i > 0 && !(i == 2));
+                main();
+                return;
+            }";
+        parser.reset(text);
+        assert!(parser.program().is_ok());
+        assert_eq!(parser.errors_built.get(), 0);
+    }
+
+    #[test]
+    fn parse_all_errors() {
+        let text = "void f() {\n  x = ;\n  y = 1;\n  z = * 2;\n}";
+        let errors = C1Parser::parse_all(text).unwrap_err();
+        let lines: Vec<usize> = errors.iter().map(|err| err.line).collect();
+        assert_eq!(lines, vec![2, 4]);
+        // parse only reports that the body ends early
+        assert_eq!(C1Parser::parse(text).unwrap_err().line, 2);
+
+        // errors in nested blocks are reported once, a broken item does not hide the next one
+        let text = "void f() {\n  if (x) { y = ; }\n  return 1\n}\nint g( {}\nint x = ;";
+        let lines: Vec<usize> = C1Parser::parse_all(text)
+            .unwrap_err()
+            .iter()
+            .map(|err| err.line)
+            .collect();
+        assert_eq!(lines, vec![2, 4, 5, 6]);
+
+        // the error is the one of the statement that was written, not of the last one tried
+        let errors = C1Parser::parse_all("void f() {\n  a = (1 +\n 2;\n}").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(
+            errors[0].message.starts_with(
+                "Unexpected token: Expected \")\", unclosed '(' opened at line 2 \n at line 3, \
+                 column 3 while trying to parse: ';'"
+            ),
+            "{}",
+            errors[0]
+        );
+        let errors = C1Parser::parse_all("void f() {\n  if (a {\n  }\n}").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].message,
+            "Unexpected token: Expected \")\", unclosed '(' opened at line 2 \n at line 2, \
+             column 9 while trying to parse: '{'"
+        );
+
+        assert_eq!(C1Parser::parse_all("void f() { x = 1; }"), Ok(()));
+    }
+
+    #[test]
+    fn best_effort() {
+        let text = "int broken( {\n  x = 1;\n}\nint x;\nvoid valid() {\n  x = 2;\n}";
+        let (functions, errors) = C1Parser::parse_best_effort(text);
+        let names: Vec<&str> = functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["valid"]);
+        assert_eq!(functions[0].body.len(), 1);
+        assert!(!errors.is_empty());
+        assert_eq!(errors[0].line, 1);
+
+        // only the broken statement is missing
+        let (functions, errors) = C1Parser::parse_best_effort("void f() { x = ; y = 1; }");
+        assert_eq!(functions.len(), 1);
+        assert_eq!(functions[0].body.len(), 1);
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn capped_errors() {
+        let text = "void f() {\n  a = ;\n  b = ;\n  c = ;\n  d = ;\n  e = ;\n}";
+        let (errors, truncated) = C1Parser::parse_errors_capped(text, 3);
+        let lines: Vec<usize> = errors.iter().map(|err| err.line).collect();
+        assert_eq!(lines, vec![2, 3, 4]);
+        assert!(truncated);
+
+        let (errors, truncated) = C1Parser::parse_errors_capped(text, 5);
+        assert_eq!(errors.len(), 5);
+        assert!(!truncated);
+        assert_eq!(
+            C1Parser::parse_errors_capped("void f() {}", 3),
+            (vec![], false)
+        );
+    }
+
+    #[test]
+    fn synchronize() {
+        // the broken assignment has no ";", the next statement keyword ends it
+        let mut parser =
+            C1Parser::initialize_parser("x = 1 +\nif (x) y = 1;\nwhile (x) {}\nreturn x; }");
+        parser.errors = Some(vec![]);
+        let statements = parser.statementlist().unwrap();
+        let kinds: Vec<&StmtKind> = statements.iter().map(|stmt| &stmt.kind).collect();
+        assert!(
+            matches!(
+                kinds[..],
+                [
+                    StmtKind::If { .. },
+                    StmtKind::While { .. },
+                    StmtKind::Return(_)
+                ]
+            ),
+            "{:?}",
+            kinds
+        );
+        assert_eq!(parser.recovered_count(), 1);
+        assert_eq!(parser.current_token(), Some(C1Token::RightBrace));
+
+        // groups are skipped as a whole, and a keyword is not a stop at the start
+        let mut parser = C1Parser::initialize_parser("return { if; } x = 1;");
+        parser.synchronize();
+        assert_eq!(parser.current_text(), Some("x"));
+    }
+
+    #[test]
+    fn lexer_error_in_message() {
+        let err = call_method(C1Parser::factor, "@").unwrap_err();
+        assert!(err.message.contains("'@' is not a valid token"), "{}", err);
+
+        let err = C1Parser::parse("void f() {}\n@").unwrap_err();
+        assert!(err.message.contains("at line 2"), "{}", err);
+        assert!(err.message.contains("'@'"), "{}", err);
+    }
+
+    #[test]
+    fn expected_operators() {
+        let mut parser = C1Parser::initialize_parser("a");
+        let ops = [C1Token::Plus, C1Token::Minus, C1Token::Or];
+        let err = parser.any_match_and_eat(&ops, "").unwrap_err();
+        assert_eq!(
+            err.message,
+            "Unexpected token: expected one of +, -, || \n at line 1, column 1 while trying to \
+             parse: 'a'"
+        );
+        assert_eq!(err.expected, ops);
+
+        let err = parser
+            .any_match_and_eat(&[C1Token::Less], "Expected comparison")
+            .unwrap_err();
+        assert!(
+            err.message
+                .contains("Expected comparison, expected one of <"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn column_in_message() {
+        let err = C1Parser::parse("void f() {\n\n\n   x = (1 + ;\n}").unwrap_err();
+        assert!(err.message.contains("at line 4, column 11"), "{}", err);
+        assert_eq!((err.line, err.column), (4, Some(11)));
+
+        let err = call_method(C1Parser::functioncall, "  \tfoo(1 2)").unwrap_err();
+        assert!(err.message.contains("at line 1, column 10 "), "{}", err);
+        let err = call_method(C1Parser::statement, "\n  5 = x;").unwrap_err();
+        assert!(err.message.contains("at line 2, column 3 "), "{}", err);
+        let err = call_method(C1Parser::factor, "  @").unwrap_err();
+        assert!(err.message.contains("at line 1, column 3 "), "{}", err);
+    }
+
+    #[test]
+    fn gcc_attributes() {
+        let result = C1Parser::parse_with_gcc_attributes("__attribute__((noreturn)) void f(){}");
+        assert!(result.is_ok(), "Parse result: {}", result.err().unwrap());
+
+        let result = C1Parser::parse_with_gcc_attributes(
+            "__attribute__((format(printf, 1, 2))) __attribute__((cold))\nint g() { return 0; }",
+        );
+        assert!(result.is_ok(), "Parse result: {}", result.err().unwrap());
+
+        let mut parser = C1Parser::initialize_parser("__attribute__((noreturn)) void");
+        parser.allow_gcc_attributes = true;
+        assert!(parser.gcc_attributes().is_ok());
+        assert_eq!(parser.current_token(), Some(C1Token::KwVoid));
+
+        assert!(C1Parser::parse("__attribute__((noreturn)) void f(){}").is_err());
+        let result = C1Parser::parse_with_gcc_attributes("__attribute__((noreturn) void f(){}");
+        assert!(result.is_err());
+        assert!(C1Parser::parse_with_gcc_attributes("__attribute__(noreturn) void f(){}").is_err());
     }
 
     #[test]
@@ -520,6 +2731,50 @@ mod tests {
         assert!(call_method(C1Parser::assignment, "1 + 2").is_ok());
     }
 
+    #[test]
+    fn valid_modulo() {
+        assert!(call_method(|p| p.binary_expr(TERM), "a % b").is_ok());
+        assert!(call_method(C1Parser::statassignment, "x = y % 2").is_ok());
+        assert!(call_method(|p| p.binary_expr(TERM), "a % b * c / d").is_ok());
+        assert!(call_method(C1Parser::assignment, "a + b % c - d").is_ok());
+        assert!(C1Parser::parse("int f() { return a % ; }").is_err());
+    }
+
+    #[test]
+    fn valid_bitwise() {
+        assert!(call_method(C1Parser::assignment, "a & b").is_ok());
+        assert!(call_method(C1Parser::assignment, "a | b ^ c").is_ok());
+        assert!(call_method(C1Parser::assignment, "a && b & c").is_ok());
+        assert!(call_method(C1Parser::assignment, "a & b == c | d").is_ok());
+        assert!(call_method(C1Parser::statassignment, "x = a & 0xFF").is_ok());
+        assert!(C1Parser::parse("int f() { return a | ; }").is_err());
+    }
+
+    #[test]
+    fn valid_shift() {
+        assert!(call_method(C1Parser::assignment, "x << 2").is_ok());
+        assert!(call_method(C1Parser::assignment, "y >> 1").is_ok());
+        assert!(call_method(C1Parser::assignment, "a << b >> c").is_ok());
+        assert!(call_method(C1Parser::assignment, "a < b").is_ok());
+        assert!(call_method(C1Parser::assignment, "a + 1 << 2 < b").is_ok());
+        assert!(C1Parser::parse("int f() { return a << ; }").is_err());
+    }
+
+    #[test]
+    fn valid_compound_statassignment() {
+        assert!(call_method(C1Parser::statassignment, "x += y + 1").is_ok());
+        assert!(call_method(C1Parser::statassignment, "x -= 2").is_ok());
+        assert!(call_method(C1Parser::statassignment, "x *= 3").is_ok());
+        assert!(call_method(C1Parser::statassignment, "x /= 4").is_ok());
+        assert!(call_method(C1Parser::statassignment, "x %= 5").is_ok());
+        assert!(call_method(C1Parser::statement, "x += y + 1;").is_ok());
+        assert!(call_method(C1Parser::statement, "x + = 1;").is_err());
+        assert!(call_method(C1Parser::statement, "5 += 1;")
+            .unwrap_err()
+            .message
+            .contains("invalid assignment target"));
+    }
+
     #[test]
     fn valid_statassignment() {
         assert!(call_method(C1Parser::statassignment, "x = y").is_ok());
@@ -527,21 +2782,306 @@ mod tests {
         assert!(call_method(C1Parser::statassignment, "x = y + t").is_ok());
     }
 
+    #[test]
+    fn valid_unary() {
+        assert!(call_method(C1Parser::unary, "-x").is_ok());
+        assert!(call_method(C1Parser::unary, "+x").is_ok());
+        assert!(call_method(C1Parser::unary, "- -x").is_ok());
+        assert!(call_method(C1Parser::unary, "-+-(x)").is_ok());
+        assert!(call_method(C1Parser::unary, "!-x").is_ok());
+        assert!(call_method(C1Parser::unary, "!+x").is_ok());
+        assert!(call_method(C1Parser::unary, "-!x").is_ok());
+        assert!(call_method(C1Parser::assignment, "3 * -x").is_ok());
+        assert!(call_method(C1Parser::assignment, "a * -b").is_ok());
+        assert!(call_method(C1Parser::statement, "x = -a + -b;").is_ok());
+
+        // binary minus followed by a unary minus
+        let mut parser = C1Parser::initialize_parser("3 - -2");
+        assert!(parser.binary_expr(SIMPEXPR).is_ok());
+        assert_eq!(parser.current_token(), None);
+
+        // in an expression `--` is a double negation, not a decrement
+        let statement = C1Parser::initialize_parser("x = --y;").statement().unwrap();
+        let StmtKind::Assign { value, .. } = statement.kind else {
+            panic!("{:?}", statement)
+        };
+        let ExprKind::Unary {
+            op: UnaryOp::Neg,
+            operand,
+        } = value.kind
+        else {
+            panic!("{:?}", value)
+        };
+        assert_eq!(value.span, Span { start: 4, end: 7 });
+        assert_eq!(operand.span, Span { start: 5, end: 7 });
+        let ExprKind::Unary {
+            op: UnaryOp::Neg,
+            operand,
+        } = operand.kind
+        else {
+            panic!("{:?}", operand)
+        };
+        assert_eq!(operand.kind, ExprKind::Variable("y".to_string()));
+        assert_eq!(parse_expr("a - --b"), parse_expr("a - - -b"));
+
+        // all prefix operators are one level, so they nest in any order
+        let expected = expr(ExprKind::Unary {
+            op: UnaryOp::Not,
+            operand: Box::new(expr(ExprKind::Unary {
+                op: UnaryOp::Neg,
+                operand: Box::new(var("x")),
+            })),
+        });
+        assert_eq!(parse_expr("!-x"), expected);
+    }
+
+    #[test]
+    fn fail_invalid_unary() {
+        assert!(call_method(C1Parser::unary, "-").is_err());
+        assert!(call_method(C1Parser::unary, "- +").is_err());
+        assert!(call_method(C1Parser::statement, "x = 3 * -;").is_err());
+    }
+
+    #[test]
+    fn logical_precedence() {
+        // each level stops in front of the operators of the looser levels
+        fn rest<T>(
+            parse_method: fn(&mut C1Parser<'static>) -> Result<T, ParseError>,
+            text: &'static str,
+        ) -> Option<String> {
+            let mut parser = C1Parser::initialize_parser(text);
+            assert!(parse_method(&mut parser).is_ok(), "{}", text);
+            parser.current_text().map(str::to_string)
+        }
+
+        assert_eq!(
+            rest(|p| p.binary_expr(LOGAND), "a || b && c"),
+            Some("||".to_string())
+        );
+        assert_eq!(
+            rest(|p| p.binary_expr(LOGAND), "b && c || a"),
+            Some("||".to_string())
+        );
+        assert_eq!(rest(|p| p.binary_expr(LOGOR), "a || b && c"), None);
+        assert_eq!(
+            rest(|p| p.binary_expr(COMPARISON), "a < b && c < d"),
+            Some("&&".to_string())
+        );
+        assert_eq!(rest(|p| p.binary_expr(LOGAND), "a < b && c < d"), None);
+        assert_eq!(
+            rest(|p| p.binary_expr(SIMPEXPR), "a + b && c"),
+            Some("&&".to_string())
+        );
+        assert_eq!(
+            rest(|p| p.binary_expr(TERM), "a * b || c"),
+            Some("||".to_string())
+        );
+        assert_eq!(rest(|p| p.binary_expr(LOGAND), "a & b && c | d"), None);
+        // comparisons do not chain, also not behind a tighter operator
+        assert_eq!(
+            rest(|p| p.binary_expr(LOGOR), "a < b < c"),
+            Some("<".to_string())
+        );
+        assert_eq!(
+            rest(|p| p.binary_expr(LOGOR), "a & b < c < d"),
+            Some("<".to_string())
+        );
+        assert_eq!(rest(|p| p.binary_expr(LOGOR), "a << b < c + d"), None);
+
+        assert!(call_method(C1Parser::statement, "x = a || b && !c;").is_ok());
+        assert!(call_method(C1Parser::statement, "x = a &&;").is_err());
+    }
+
+    #[test]
+    fn ternary() {
+        assert!(call_method(C1Parser::assignment, "a ? b : c").is_ok());
+        assert!(call_method(C1Parser::assignment, "a ? b : c ? d : e").is_ok());
+        assert!(call_method(C1Parser::assignment, "a ? b ? c : d : e").is_ok());
+        assert!(call_method(C1Parser::statement, "y = x > 0 ? 1 : -1;").is_ok());
+        assert!(call_method(C1Parser::statement, "y = (a ? b : c) + 1;").is_ok());
+
+        let err = call_method(C1Parser::ternary, "a ? b").unwrap_err();
+        assert!(err.message.contains(r#"Expected ":""#), "{}", err);
+        assert!(call_method(C1Parser::statement, "y = a ? : c;").is_err());
+        assert!(call_method(C1Parser::statement, "y = a ? b : ;").is_err());
+    }
+
+    #[test]
+    fn ternary_assignment() {
+        // the whole conditional is assigned, the assignment does not become its condition
+        let variable = |name: &str| ExprKind::Variable(name.to_string());
+        let statement = C1Parser::initialize_parser("x = a ? b : c;")
+            .statement()
+            .unwrap();
+        let StmtKind::Assign {
+            target,
+            index: None,
+            op: AssignOp::Assign,
+            value,
+        } = statement.kind
+        else {
+            panic!("{:?}", statement)
+        };
+        assert_eq!(target, "x");
+        let ExprKind::Conditional {
+            condition,
+            then_value,
+            else_value,
+        } = value.kind
+        else {
+            panic!("{:?}", value)
+        };
+        assert_eq!(condition.kind, variable("a"));
+        assert_eq!(then_value.kind, variable("b"));
+        assert_eq!(else_value.kind, variable("c"));
+
+        let ExprKind::Assign { target, value } = parse_expr("x = a ? b : c").kind else {
+            panic!()
+        };
+        assert_eq!(target, "x");
+        assert!(
+            matches!(value.kind, ExprKind::Conditional { .. }),
+            "{:?}",
+            value
+        );
+    }
+
     #[test]
     fn valid_factor() {
         assert!(call_method(C1Parser::factor, "4").is_ok());
+        assert!(call_method(C1Parser::factor, "0xdeadBEEF").is_ok());
+        assert!(call_method(C1Parser::factor, "0b1010").is_ok());
         assert!(call_method(C1Parser::factor, "1.2").is_ok());
         assert!(call_method(C1Parser::factor, "true").is_ok());
+        assert!(call_method(C1Parser::factor, "'c'").is_ok());
+        assert!(call_method(C1Parser::factor, r#""msg""#).is_ok());
         assert!(call_method(C1Parser::factor, "foo()").is_ok());
         assert!(call_method(C1Parser::factor, "x").is_ok());
         assert!(call_method(C1Parser::factor, "(x + y)").is_ok());
     }
 
+    #[test]
+    fn valid_not() {
+        assert!(call_method(C1Parser::unary, "!x").is_ok());
+        assert!(call_method(C1Parser::unary, "!foo()").is_ok());
+        assert!(call_method(C1Parser::unary, "!(a == b)").is_ok());
+        assert!(call_method(C1Parser::unary, "!!x").is_ok());
+        assert!(call_method(C1Parser::assignment, "a != b").is_ok());
+        assert!(call_method(C1Parser::ifstatement, "if(!flag) {}").is_ok());
+        assert!(call_method(C1Parser::unary, "!").is_err());
+        assert!(call_method(C1Parser::factor, "!x").is_err());
+    }
+
     #[test]
     fn fail_invalid_factor() {
         assert!(call_method(C1Parser::factor, "if").is_err());
         assert!(call_method(C1Parser::factor, "(x +").is_err());
         assert!(call_method(C1Parser::factor, "bool").is_err());
+        assert!(call_method(C1Parser::factor, "0x").is_err());
+        assert!(call_method(C1Parser::factor, "0b2").is_err());
+    }
+
+    #[test]
+    fn largest_function_by_statements() {
+        let result = C1Parser::largest_function(
+            "void a() { x = 1; }\n\
+            int b() { x = 1; { y = 2; z = 3; } return x; }\n\
+            void c() {}",
+        );
+        assert_eq!(result, Ok(Some(("b".to_string(), 3))));
+
+        assert_eq!(C1Parser::largest_function(""), Ok(None));
+        let err = C1Parser::largest_function("int f( {}").unwrap_err();
+        assert!(err.contains("line 1"), "{}", err);
+    }
+
+    #[test]
+    fn function_signature_by_name() {
+        let text = "int g;\nvoid f() {}\nint add(int a, int b) { return a + b; }";
+        let signature = C1Parser::function_signature(text, "add");
+        assert_eq!(
+            signature,
+            Ok(Some(Signature {
+                return_type: Type::Int,
+                parameter_types: vec![Type::Int, Type::Int],
+                parameter_names: vec!["a".to_string(), "b".to_string()],
+            }))
+        );
+        let signature = C1Parser::function_signature(text, "f").unwrap().unwrap();
+        assert_eq!(signature.return_type, Type::Void);
+        assert!(signature.parameter_names.is_empty());
+
+        // globals are not functions
+        assert_eq!(C1Parser::function_signature(text, "g"), Ok(None));
+        assert_eq!(C1Parser::function_signature(text, "missing"), Ok(None));
+        assert!(C1Parser::function_signature("int f( {}", "f").is_err());
+    }
+
+    #[test]
+    fn structural_hash() {
+        assert_eq!(
+            C1Parser::structural_hash("int x = 1;"),
+            C1Parser::structural_hash("int y = 2;")
+        );
+        assert_eq!(
+            C1Parser::structural_hash("int x = 1;"),
+            C1Parser::structural_hash("int  x=1; // comment")
+        );
+        assert_ne!(
+            C1Parser::structural_hash("int x = 1;"),
+            C1Parser::structural_hash("float x = 1;")
+        );
+        assert!(C1Parser::structural_hash("int x = @;").is_err());
+    }
+
+    #[test]
+    fn binary_literals_in_program() {
+        let result = C1Parser::parse("int mask() { x = 0b0; y = 0b1111 * 0xF + 7; return x + y; }");
+        assert!(result.is_ok(), "Parse result: {}", result.err().unwrap());
+    }
+
+    #[test]
+    fn lsp_diagnostics() {
+        assert_eq!(C1Parser::lsp_diagnostics("void main() {}"), "[]");
+
+        // the range is the one of the offending ";"
+        let json = C1Parser::lsp_diagnostics("void main() {\n    x = 1;\n    y = ;\n}");
+        assert!(json.starts_with('[') && json.ends_with(']'), "{}", json);
+        assert!(
+            json.contains(r#""start":{"line":2,"character":8},"end":{"line":2,"character":9}"#),
+            "{}",
+            json
+        );
+        assert!(json.contains(r#""severity":1"#), "{}", json);
+        assert_eq!(json.matches(r#""range""#).count(), 1);
+
+        // every error is reported, at the end of the input with an empty range
+        let json = C1Parser::lsp_diagnostics("void f() {\n  x = ;\n  y = ;\n");
+        assert_eq!(json.matches(r#""severity":1"#).count(), 3, "{}", json);
+        assert!(
+            json.contains(r#""start":{"line":2,"character":6},"end":{"line":2,"character":7}"#),
+            "{}",
+            json
+        );
+        assert!(
+            json.contains(r#""start":{"line":2,"character":7},"end":{"line":2,"character":7}"#),
+            "{}",
+            json
+        );
+
+        // lints are warnings
+        let json = C1Parser::lsp_diagnostics("int f() {\n  return 1;\n  x = y;\n}");
+        assert_eq!(
+            json,
+            concat!(
+                r#"[{"range":{"start":{"line":2,"character":2},"end":{"line":2,"character":8}},"#,
+                r#""severity":2,"source":"cb-3","message":"Unreachable code"},"#,
+                r#"{"range":{"start":{"line":2,"character":2},"end":{"line":2,"character":3}},"#,
+                r#""severity":2,"source":"cb-3","message":"Undeclared variable 'x'"},"#,
+                r#"{"range":{"start":{"line":2,"character":6},"end":{"line":2,"character":7}},"#,
+                r#""severity":2,"source":"cb-3","message":"Undeclared variable 'y'"}]"#
+            )
+        );
     }
 
     #[test]
@@ -552,4 +3092,332 @@ mod tests {
         )
         .is_ok());
     }
+
+    fn parse_expr(text: &'static str) -> Expr {
+        let mut parser = C1Parser::initialize_parser(text);
+        let mut expr = parser.assignment().unwrap();
+        assert_eq!(parser.current_token(), None, "{}", text);
+        clear_expr_spans(&mut expr);
+        expr
+    }
+
+    fn parse_program(text: &'static str) -> Program {
+        let mut program = C1Parser::parse_to_ast(text).unwrap();
+        for item in &mut program.items {
+            match item {
+                Item::Function(function) => {
+                    function.span = Span::default();
+                    function
+                        .parameters
+                        .iter_mut()
+                        .for_each(|p| p.span = Span::default());
+                    function.body.iter_mut().for_each(clear_stmt_spans);
+                }
+                Item::Global(declaration) => clear_declaration_spans(declaration),
+            }
+        }
+        program
+    }
+
+    /// Reset all spans so that trees can be compared structurally
+    fn clear_stmt_spans(stmt: &mut Stmt) {
+        stmt.span = Span::default();
+        match &mut stmt.kind {
+            StmtKind::Block(statements) => statements.iter_mut().for_each(clear_stmt_spans),
+            StmtKind::Declaration(declaration) => clear_declaration_spans(declaration),
+            StmtKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                clear_expr_spans(condition);
+                clear_stmt_spans(then_branch);
+                else_branch
+                    .iter_mut()
+                    .for_each(|stmt| clear_stmt_spans(stmt));
+            }
+            StmtKind::While { condition, body } | StmtKind::DoWhile { body, condition } => {
+                clear_expr_spans(condition);
+                clear_stmt_spans(body);
+            }
+            StmtKind::For {
+                init,
+                condition,
+                update,
+                body,
+            } => {
+                init.iter_mut().for_each(|stmt| clear_stmt_spans(stmt));
+                clear_expr_spans(condition);
+                update.iter_mut().for_each(|stmt| clear_stmt_spans(stmt));
+                clear_stmt_spans(body);
+            }
+            StmtKind::Return(value) => value.iter_mut().for_each(clear_expr_spans),
+            StmtKind::Printf(arguments) => arguments.iter_mut().for_each(clear_expr_spans),
+            StmtKind::Assign { index, value, .. } => {
+                index.iter_mut().for_each(clear_expr_spans);
+                clear_expr_spans(value);
+            }
+            StmtKind::Expr(value) => clear_expr_spans(value),
+            StmtKind::Empty
+            | StmtKind::Break
+            | StmtKind::Continue
+            | StmtKind::Asm(_)
+            | StmtKind::IncDec { .. } => {}
+        }
+    }
+
+    fn clear_declaration_spans(declaration: &mut Declaration) {
+        declaration.span = Span::default();
+        for variable in &mut declaration.variables {
+            variable.span = Span::default();
+            variable.initializer.iter_mut().for_each(clear_expr_spans);
+        }
+    }
+
+    fn clear_expr_spans(expr: &mut Expr) {
+        expr.span = Span::default();
+        match &mut expr.kind {
+            ExprKind::Index { index: operand, .. }
+            | ExprKind::Unary { operand, .. }
+            | ExprKind::Assign { value: operand, .. } => clear_expr_spans(operand),
+            ExprKind::Binary { left, right, .. } => {
+                clear_expr_spans(left);
+                clear_expr_spans(right);
+            }
+            ExprKind::Call { arguments, .. } => arguments.iter_mut().for_each(clear_expr_spans),
+            ExprKind::Conditional {
+                condition,
+                then_value,
+                else_value,
+            } => {
+                clear_expr_spans(condition);
+                clear_expr_spans(then_value);
+                clear_expr_spans(else_value);
+            }
+            _ => {}
+        }
+    }
+
+    fn expr(kind: ExprKind) -> Expr {
+        Expr {
+            kind,
+            span: Span::default(),
+        }
+    }
+
+    fn stmt(kind: StmtKind) -> Stmt {
+        Stmt {
+            kind,
+            span: Span::default(),
+        }
+    }
+
+    fn binary(op: BinaryOp, left: Expr, right: Expr) -> Expr {
+        expr(ExprKind::Binary {
+            op,
+            left: Box::new(left),
+            right: Box::new(right),
+        })
+    }
+
+    fn var(name: &str) -> Expr {
+        expr(ExprKind::Variable(name.to_string()))
+    }
+
+    fn int(value: i64) -> Expr {
+        expr(ExprKind::Int(value))
+    }
+
+    #[test]
+    fn ast_function() {
+        let program = parse_program("int f() { return 1 + 2; }");
+        assert_eq!(
+            program,
+            Program {
+                items: vec![Item::Function(Function {
+                    return_type: Type::Int,
+                    name: "f".to_string(),
+                    parameters: vec![],
+                    body: vec![stmt(StmtKind::Return(Some(binary(
+                        BinaryOp::Add,
+                        int(1),
+                        int(2)
+                    ))))],
+                    span: Span::default(),
+                })],
+            }
+        );
+
+        let program = parse_program("int x = 1; void g(float a) { x++; g(); }");
+        let [Item::Global(global), Item::Function(function)] = &program.items[..] else {
+            panic!("{:?}", program)
+        };
+        assert_eq!(global.ty, Type::Int);
+        assert_eq!(global.variables[0].initializer, Some(int(1)));
+        assert_eq!(
+            function.parameters,
+            vec![Parameter {
+                ty: Type::Float,
+                name: "a".to_string(),
+                span: Span::default(),
+            }]
+        );
+        assert_eq!(
+            function.body,
+            vec![
+                stmt(StmtKind::IncDec {
+                    target: "x".to_string(),
+                    op: IncDecOp::Increment,
+                    prefix: false
+                }),
+                stmt(StmtKind::Expr(expr(ExprKind::Call {
+                    name: "g".to_string(),
+                    arguments: vec![],
+                }))),
+            ]
+        );
+    }
+
+    #[test]
+    fn ast_statements() {
+        let program =
+            parse_program("void f() { if (a) { b += 1; } else ; while (c) {} int d[2], e; }");
+        let Item::Function(function) = &program.items[0] else {
+            panic!("{:?}", program)
+        };
+        assert_eq!(
+            function.body,
+            vec![
+                stmt(StmtKind::If {
+                    condition: var("a"),
+                    then_branch: Box::new(stmt(StmtKind::Block(vec![stmt(StmtKind::Assign {
+                        target: "b".to_string(),
+                        index: None,
+                        op: AssignOp::Add,
+                        value: int(1),
+                    })]))),
+                    else_branch: Some(Box::new(stmt(StmtKind::Empty))),
+                }),
+                stmt(StmtKind::While {
+                    condition: var("c"),
+                    body: Box::new(stmt(StmtKind::Block(vec![]))),
+                }),
+                stmt(StmtKind::Declaration(Declaration {
+                    ty: Type::Int,
+                    variables: vec![
+                        Variable {
+                            name: "d".to_string(),
+                            array_size: Some(2),
+                            initializer: None,
+                            span: Span::default(),
+                        },
+                        Variable {
+                            name: "e".to_string(),
+                            array_size: None,
+                            initializer: None,
+                            span: Span::default(),
+                        },
+                    ],
+                    span: Span::default(),
+                })),
+            ]
+        );
+    }
+
+    #[test]
+    fn ast_expressions() {
+        // binary operators group from the left
+        let expected = binary(
+            BinaryOp::Sub,
+            binary(BinaryOp::Sub, var("a"), var("b")),
+            var("c"),
+        );
+        assert_eq!(parse_expr("a - b - c"), expected);
+
+        let expected = binary(
+            BinaryOp::Add,
+            var("a"),
+            binary(BinaryOp::Mul, var("b"), var("c")),
+        );
+        assert_eq!(parse_expr("a + b * c"), expected);
+
+        let expected = binary(
+            BinaryOp::Or,
+            var("a"),
+            binary(BinaryOp::And, var("b"), var("c")),
+        );
+        assert_eq!(parse_expr("a || b && c"), expected);
+
+        let expected = expr(ExprKind::Unary {
+            op: UnaryOp::Neg,
+            operand: Box::new(expr(ExprKind::Unary {
+                op: UnaryOp::Not,
+                operand: Box::new(var("x")),
+            })),
+        });
+        assert_eq!(parse_expr("-!x"), expected);
+
+        assert_eq!(parse_expr("0x1F"), int(31));
+        assert_eq!(parse_expr("0b101"), int(5));
+        assert_eq!(parse_expr("1_000"), int(1000));
+        assert_eq!(parse_expr("25e-1"), expr(ExprKind::Float(2.5)));
+        assert_eq!(parse_expr(".5"), expr(ExprKind::Float(0.5)));
+        assert_eq!(parse_expr("'\\n'"), expr(ExprKind::Char('\n')));
+        assert_eq!(
+            parse_expr(r#""a\"b""#),
+            expr(ExprKind::String("a\"b".to_string()))
+        );
+        assert!(call_method(C1Parser::factor, "99999999999999999999").is_err());
+    }
+
+    #[test]
+    fn ast_chained_assignment() {
+        let assign = |target: &str, value| {
+            expr(ExprKind::Assign {
+                target: target.to_string(),
+                value: Box::new(value),
+            })
+        };
+        assert_eq!(parse_expr("a = b = 1"), assign("a", assign("b", int(1))));
+        assert_eq!(
+            parse_expr("a = b = c = 1"),
+            assign("a", assign("b", assign("c", int(1))))
+        );
+    }
+
+    #[test]
+    fn ast_spans() {
+        let mut parser = C1Parser::initialize_parser("x = (1 + 2) * y");
+        let assignment = parser.assignment().unwrap();
+        assert_eq!(assignment.span, Span { start: 0, end: 15 });
+        let ExprKind::Assign { value, .. } = assignment.kind else {
+            panic!("{:?}", assignment)
+        };
+        let ExprKind::Binary { left, right, .. } = value.kind else {
+            panic!("{:?}", value)
+        };
+        assert_eq!(value.span, Span { start: 4, end: 15 });
+        assert_eq!(left.span, Span { start: 4, end: 11 });
+        assert_eq!(right.span, Span { start: 14, end: 15 });
+
+        let mut parser = C1Parser::initialize_parser("1 + 2");
+        assert_eq!(parser.assignment().unwrap().span, Span { start: 0, end: 5 });
+
+        let text = "int f(int a) {\n  return a;\n}";
+        let program = C1Parser::parse_to_ast(text).unwrap();
+        let Item::Function(function) = &program.items[0] else {
+            panic!("{:?}", program)
+        };
+        assert_eq!(
+            function.span,
+            Span {
+                start: 0,
+                end: text.len()
+            }
+        );
+        let span = function.parameters[0].span;
+        assert_eq!(&text[span.start..span.end], "int a");
+        let span = function.body[0].span;
+        assert_eq!(&text[span.start..span.end], "return a;");
+    }
 }
@@ -0,0 +1,311 @@
+use crate::ast::{Declaration, Function, Item, Program, Span, Type};
+use std::collections::HashMap;
+
+/// What a name refers to
+#[derive(Debug, Clone, PartialEq)]
+pub enum SymbolInfo {
+    Variable {
+        ty: Type,
+        /// The size of an array variable, `None` for a scalar
+        array_size: Option<usize>,
+        span: Span,
+    },
+    Function {
+        return_type: Type,
+        parameters: Vec<Type>,
+        span: Span,
+    },
+}
+
+/// Names visible at some point of a program, organized in nested lexical scopes. Functions and
+/// variables share one namespace, as in C, so a local variable can shadow a function.
+///
+/// The outermost scope holds the functions and global variables. A pass walking the AST enters a
+/// function with `enter_function`, opens and closes a scope for every nested block and declares
+/// local variables as it reaches them.
+///
+/// ```
+/// use cb_3::ast::{Item, Type};
+/// use cb_3::symbols::{SymbolInfo, SymbolTable};
+/// use cb_3::C1Parser;
+///
+/// let program = C1Parser::parse_to_ast("int x; void f(float x) {}").unwrap();
+/// let mut symbols = SymbolTable::new(&program);
+/// let Item::Function(f) = &program.items[1] else { unreachable!() };
+/// symbols.enter_function(f);
+/// assert!(matches!(symbols.lookup("x"), Some(SymbolInfo::Variable { ty: Type::Float, .. })));
+/// symbols.exit_scope();
+/// assert!(matches!(symbols.lookup("x"), Some(SymbolInfo::Variable { ty: Type::Int, .. })));
+/// ```
+#[derive(Debug, Clone)]
+pub struct SymbolTable {
+    /// Innermost scope last
+    scopes: Vec<HashMap<String, SymbolInfo>>,
+    redefinitions: Vec<Redefinition>,
+}
+
+impl Default for SymbolTable {
+    /// A table with only the empty outermost scope
+    fn default() -> Self {
+        SymbolTable {
+            scopes: vec![HashMap::new()],
+            redefinitions: vec![],
+        }
+    }
+}
+
+/// A function defined with the name of an earlier function. Functions cannot be overloaded, so
+/// the parameters do not matter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redefinition {
+    pub name: String,
+    /// Span of the first definition
+    pub original: Span,
+    /// Span of the later definition
+    pub duplicate: Span,
+}
+
+impl Redefinition {
+    /// Describe the redefinition with the line numbers of both definitions in `text`
+    pub fn message(&self, text: &str) -> String {
+        format!(
+            "Function '{}' is already defined at line {}, redefined at line {}",
+            self.name,
+            self.original.line_column(text).0,
+            self.duplicate.line_column(text).0
+        )
+    }
+}
+
+impl SymbolTable {
+    /// Create a table with the functions and global variables of a program in scope. A later
+    /// definition of the same name replaces an earlier one, except for a function defined twice:
+    /// the first definition is kept and the second is recorded in `redefinitions`.
+    pub fn new(program: &Program) -> Self {
+        let mut table = SymbolTable::default();
+        for item in &program.items {
+            match item {
+                Item::Function(function) => {
+                    let previous = table.define_function(function);
+                    if let Some(SymbolInfo::Function { span, .. }) = previous {
+                        table.redefinitions.push(Redefinition {
+                            name: function.name.clone(),
+                            original: span,
+                            duplicate: function.span,
+                        });
+                        table.define(&function.name, previous.unwrap());
+                    }
+                }
+                Item::Global(declaration) => {
+                    table.declare(declaration);
+                }
+            }
+        }
+        table
+    }
+
+    /// Functions that were defined more than once, in the order of their second definition
+    pub fn redefinitions(&self) -> &[Redefinition] {
+        &self.redefinitions
+    }
+
+    /// Open a new innermost scope
+    pub fn enter_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    /// Open the scope of a function body with its parameters defined
+    pub fn enter_function(&mut self, function: &Function) {
+        self.enter_scope();
+        for parameter in &function.parameters {
+            let info = SymbolInfo::Variable {
+                ty: parameter.ty,
+                array_size: None,
+                span: parameter.span,
+            };
+            self.define(&parameter.name, info);
+        }
+    }
+
+    /// Close the innermost scope, forgetting everything defined in it. The outermost scope is
+    /// never closed.
+    pub fn exit_scope(&mut self) {
+        if self.scopes.len() > 1 {
+            self.scopes.pop();
+        }
+    }
+
+    /// Number of open scopes, 1 outside of any function
+    pub fn depth(&self) -> usize {
+        self.scopes.len()
+    }
+
+    /// Define a name in the innermost scope. Returns the symbol it replaces in that scope, symbols
+    /// of outer scopes are only shadowed.
+    pub fn define(&mut self, name: &str, info: SymbolInfo) -> Option<SymbolInfo> {
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.to_string(), info)
+    }
+
+    /// Define the signature of a function in the innermost scope and return the symbol it replaces
+    pub fn define_function(&mut self, function: &Function) -> Option<SymbolInfo> {
+        let info = SymbolInfo::Function {
+            return_type: function.return_type,
+            parameters: function.parameters.iter().map(|p| p.ty).collect(),
+            span: function.span,
+        };
+        self.define(&function.name, info)
+    }
+
+    /// Define all variables of a declaration in the innermost scope and return the symbols they
+    /// replace
+    pub fn declare(&mut self, declaration: &Declaration) -> Vec<(String, SymbolInfo)> {
+        let mut replaced = vec![];
+        for variable in &declaration.variables {
+            let info = SymbolInfo::Variable {
+                ty: declaration.ty,
+                array_size: variable.array_size,
+                span: variable.span,
+            };
+            if let Some(previous) = self.define(&variable.name, info) {
+                replaced.push((variable.name.clone(), previous));
+            }
+        }
+        replaced
+    }
+
+    /// Find the innermost definition of a name
+    pub fn lookup(&self, name: &str) -> Option<SymbolInfo> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{Item, Program, StmtKind, Type};
+    use crate::symbols::{SymbolInfo, SymbolTable};
+    use crate::C1Parser;
+
+    fn variable_type(symbols: &SymbolTable, name: &str) -> Option<Type> {
+        match symbols.lookup(name) {
+            Some(SymbolInfo::Variable { ty, .. }) => Some(ty),
+            _ => None,
+        }
+    }
+
+    /// Enter the last function of the program and declare its top-level locals
+    fn enter_f(program: &Program) -> SymbolTable {
+        let mut symbols = SymbolTable::new(program);
+        let Item::Function(function) = &program.items.last().unwrap() else {
+            panic!("{:?}", program)
+        };
+        symbols.enter_function(function);
+        for stmt in &function.body {
+            if let StmtKind::Declaration(declaration) = &stmt.kind {
+                symbols.declare(declaration);
+            }
+        }
+        symbols
+    }
+
+    #[test]
+    fn globals_and_functions() {
+        let program =
+            C1Parser::parse_to_ast("int a[3]; bool g(int x, float y) { return true; }").unwrap();
+        let symbols = SymbolTable::new(&program);
+        assert_eq!(symbols.depth(), 1);
+        assert!(matches!(
+            symbols.lookup("a"),
+            Some(SymbolInfo::Variable {
+                ty: Type::Int,
+                array_size: Some(3),
+                ..
+            })
+        ));
+        let Some(SymbolInfo::Function {
+            return_type,
+            parameters,
+            ..
+        }) = symbols.lookup("g")
+        else {
+            panic!("{:?}", symbols)
+        };
+        assert_eq!(return_type, Type::Bool);
+        assert_eq!(parameters, vec![Type::Int, Type::Float]);
+        assert_eq!(symbols.lookup("x"), None);
+    }
+
+    #[test]
+    fn duplicate_functions() {
+        let text = "int foo() { return 1; }\nvoid bar() {}\nvoid foo(int x) {}";
+        let program = C1Parser::parse_to_ast(text).unwrap();
+        let symbols = SymbolTable::new(&program);
+        assert_eq!(symbols.redefinitions().len(), 1);
+        assert_eq!(
+            symbols.redefinitions()[0].message(text),
+            "Function 'foo' is already defined at line 1, redefined at line 3"
+        );
+        // the first definition wins
+        assert!(matches!(
+            symbols.lookup("foo"),
+            Some(SymbolInfo::Function {
+                return_type: Type::Int,
+                ..
+            })
+        ));
+
+        let program = C1Parser::parse_to_ast("int x; void x() {}").unwrap();
+        assert!(SymbolTable::new(&program).redefinitions().is_empty());
+    }
+
+    #[test]
+    fn block_scopes() {
+        let program =
+            C1Parser::parse_to_ast("void f() { int x; { float x; bool inner; } }").unwrap();
+        let mut symbols = enter_f(&program);
+        let Item::Function(function) = &program.items[0] else {
+            panic!("{:?}", program)
+        };
+        let StmtKind::Block(block) = &function.body[1].kind else {
+            panic!("{:?}", function)
+        };
+
+        symbols.enter_scope();
+        for stmt in block {
+            if let StmtKind::Declaration(declaration) = &stmt.kind {
+                symbols.declare(declaration);
+            }
+        }
+        // the inner x shadows the outer one
+        assert_eq!(variable_type(&symbols, "x"), Some(Type::Float));
+        assert_eq!(variable_type(&symbols, "inner"), Some(Type::Bool));
+
+        symbols.exit_scope();
+        assert_eq!(variable_type(&symbols, "x"), Some(Type::Int));
+        assert_eq!(symbols.lookup("inner"), None);
+    }
+
+    #[test]
+    fn shadowing() {
+        let program = C1Parser::parse_to_ast("int x; void f(bool x) { float f; }").unwrap();
+        let mut symbols = enter_f(&program);
+        assert_eq!(variable_type(&symbols, "x"), Some(Type::Bool));
+        // a variable can shadow a function
+        assert_eq!(variable_type(&symbols, "f"), Some(Type::Float));
+
+        symbols.exit_scope();
+        assert_eq!(variable_type(&symbols, "x"), Some(Type::Int));
+        assert!(matches!(
+            symbols.lookup("f"),
+            Some(SymbolInfo::Function { .. })
+        ));
+        symbols.exit_scope();
+        assert_eq!(symbols.depth(), 1);
+    }
+}
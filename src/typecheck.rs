@@ -0,0 +1,567 @@
+use crate::ast::{
+    AssignOp, BinaryOp, Declaration, Expr, ExprKind, Function, Program, Span, Stmt, StmtKind, Type,
+    UnaryOp,
+};
+use crate::symbols::{SymbolInfo, SymbolTable};
+use crate::visitor::{walk_program, walk_program_mut, Visitor, VisitorMut};
+use std::fmt;
+
+/// A type error and the span of the offending node
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at {}..{}",
+            self.message, self.span.start, self.span.end
+        )
+    }
+}
+
+/// Check that every expression is used with a matching type. `int` converts to `float`
+/// implicitly, no other conversions exist. Conditions have to be `bool`, arithmetic needs numeric
+/// operands and `return` has to match the return type of its function.
+///
+/// Uses of undeclared names are not reported here, nothing is checked about them.
+pub fn typecheck(program: &Program) -> Result<(), Vec<TypeError>> {
+    let checker = Checker::check(program);
+    if checker.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(checker.errors)
+    }
+}
+
+/// Replace every `/` whose operand types are known by [`BinaryOp::IntDiv`] if both are `int` or
+/// [`BinaryOp::FloatDiv`] otherwise. Divisions of operands with type errors stay
+/// [`BinaryOp::Div`].
+pub fn tag_divisions(program: &mut Program) {
+    let checker = Checker::check(program);
+    let mut tagger = Tagger {
+        divisions: checker.divisions.into_iter().peekable(),
+    };
+    walk_program_mut(&mut tagger, program);
+}
+
+/// Sets the operators found by the checker, which walks the nodes in the same order
+struct Tagger {
+    divisions: std::iter::Peekable<std::vec::IntoIter<(Span, BinaryOp)>>,
+}
+
+impl VisitorMut for Tagger {
+    fn leave_expr(&mut self, expr: &mut Expr) {
+        if let ExprKind::Binary {
+            op: op @ BinaryOp::Div,
+            ..
+        } = &mut expr.kind
+        {
+            if let Some((_, tag)) = self.divisions.next_if(|(span, _)| *span == expr.span) {
+                *op = tag;
+            }
+        }
+    }
+}
+
+/// Every expression is checked once its operands are, their types are passed up on `types`
+struct Checker {
+    symbols: SymbolTable,
+    /// Return type of the function being checked
+    return_type: Type,
+    /// Types of the expressions walked so far whose parent is not left yet, the last one
+    /// innermost. `None` if it is unknown because of an earlier error.
+    types: Vec<Option<Type>>,
+    errors: Vec<TypeError>,
+    /// Every `/` with known operand types and the operator it is tagged with, in walk order
+    divisions: Vec<(Span, BinaryOp)>,
+}
+
+impl Visitor for Checker {
+    fn visit_function(&mut self, function: &Function) {
+        self.return_type = function.return_type;
+        self.symbols.enter_function(function);
+    }
+
+    fn leave_function(&mut self, _function: &Function) {
+        self.symbols.exit_scope();
+    }
+
+    fn enter_scope(&mut self) {
+        self.symbols.enter_scope();
+    }
+
+    fn leave_scope(&mut self) {
+        self.symbols.exit_scope();
+    }
+
+    /// Check the initializers of a declaration, then bring its variables into scope
+    fn leave_declaration(&mut self, declaration: &Declaration) {
+        let count = declaration
+            .variables
+            .iter()
+            .filter(|variable| variable.initializer.is_some())
+            .count();
+        let mut types = self.pop(count).into_iter();
+        for variable in &declaration.variables {
+            if declaration.ty == Type::Void {
+                let message = format!("Variable '{}' cannot be void", variable.name);
+                self.error(variable.span, message);
+            }
+            if let Some(initializer) = &variable.initializer {
+                let ty = types.next().unwrap();
+                if let Some(ty) = self.value(ty, initializer) {
+                    self.expect(declaration.ty, ty, initializer);
+                }
+            }
+        }
+        self.symbols.declare(declaration);
+    }
+
+    fn leave_stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::If { condition, .. }
+            | StmtKind::While { condition, .. }
+            | StmtKind::DoWhile { condition, .. }
+            | StmtKind::For { condition, .. } => {
+                let ty = self.pop(1)[0];
+                self.condition(ty, condition);
+            }
+            StmtKind::Return(value) => {
+                let ty = value.as_ref().and_then(|_| self.pop(1)[0]);
+                match (self.return_type, value) {
+                    (Type::Void, None) => {}
+                    (Type::Void, Some(_)) => {
+                        self.error(stmt.span, "Void function cannot return a value".to_string())
+                    }
+                    (expected, None) => {
+                        let message = format!("Function has to return a {:?}", expected);
+                        self.error(stmt.span, message)
+                    }
+                    (expected, Some(value)) => {
+                        if let Some(ty) = self.value(ty, value) {
+                            self.expect(expected, ty, value);
+                        }
+                    }
+                }
+            }
+            StmtKind::Printf(arguments) => {
+                let types = self.pop(arguments.len());
+                for (argument, ty) in arguments.iter().zip(types) {
+                    // string literals are only allowed here
+                    if !matches!(argument.kind, ExprKind::String(_)) {
+                        self.value(ty, argument);
+                    }
+                }
+            }
+            StmtKind::Assign {
+                target,
+                index,
+                op,
+                value,
+            } => {
+                let value_type = self.pop(1)[0];
+                let target_type = match index {
+                    Some(index) => {
+                        let index_type = self.pop(1)[0];
+                        self.element(target, index, index_type, stmt.span)
+                    }
+                    None => self.variable(target, stmt.span),
+                };
+                let value_type = self.value(value_type, value);
+                let op = match op {
+                    AssignOp::Assign => None,
+                    AssignOp::Add => Some(BinaryOp::Add),
+                    AssignOp::Sub => Some(BinaryOp::Sub),
+                    AssignOp::Mul => Some(BinaryOp::Mul),
+                    AssignOp::Div => Some(BinaryOp::Div),
+                    AssignOp::Rem => Some(BinaryOp::Rem),
+                };
+                let value_type = match (op, target_type, value_type) {
+                    (None, _, value_type) => value_type,
+                    (Some(op), Some(left), Some(right)) => self.binary(op, left, right, stmt.span),
+                    _ => None,
+                };
+                if let (Some(target_type), Some(value_type)) = (target_type, value_type) {
+                    self.expect(target_type, value_type, value);
+                }
+            }
+            StmtKind::IncDec { target, .. } => {
+                if let Some(ty @ Type::Bool) = self.variable(target, stmt.span) {
+                    let message = format!("Cannot increment or decrement a {:?}", ty);
+                    self.error(stmt.span, message);
+                }
+            }
+            StmtKind::Expr(_) => {
+                self.pop(1);
+            }
+            StmtKind::Block(_)
+            | StmtKind::Declaration(_)
+            | StmtKind::Empty
+            | StmtKind::Break
+            | StmtKind::Continue
+            | StmtKind::Asm(_) => {}
+        }
+    }
+
+    fn leave_expr(&mut self, expr: &Expr) {
+        let ty = self.expr(expr);
+        self.types.push(ty);
+    }
+}
+
+impl Checker {
+    fn check(program: &Program) -> Checker {
+        let mut checker = Checker {
+            symbols: SymbolTable::new(program),
+            return_type: Type::Void,
+            types: vec![],
+            errors: vec![],
+            divisions: vec![],
+        };
+        walk_program(&mut checker, program);
+        checker
+    }
+
+    fn error(&mut self, span: Span, message: String) {
+        self.errors.push(TypeError { message, span });
+    }
+
+    /// Take the types of the last `count` expressions, in source order
+    fn pop(&mut self, count: usize) -> Vec<Option<Type>> {
+        self.types.split_off(self.types.len() - count)
+    }
+
+    fn condition(&mut self, ty: Option<Type>, condition: &Expr) {
+        if let Some(ty) = self.value(ty, condition) {
+            if ty != Type::Bool {
+                let message = format!("Condition has to be a Bool, found {:?}", ty);
+                self.error(condition.span, message);
+            }
+        }
+    }
+
+    /// Report an error unless a value of type `ty` can be used where `expected` is required
+    fn expect(&mut self, expected: Type, ty: Type, expr: &Expr) {
+        if !(ty == expected || (ty == Type::Int && expected == Type::Float)) {
+            let message = format!("Expected a value of type {:?}, found {:?}", expected, ty);
+            self.error(expr.span, message);
+        }
+    }
+
+    /// The type of a scalar variable, `None` if the name is not declared
+    fn variable(&mut self, name: &str, span: Span) -> Option<Type> {
+        match self.symbols.lookup(name)? {
+            SymbolInfo::Variable {
+                ty,
+                array_size: None,
+                ..
+            } => Some(ty),
+            SymbolInfo::Variable { .. } => {
+                self.error(span, format!("Array '{}' has to be indexed", name));
+                None
+            }
+            SymbolInfo::Function { .. } => {
+                self.error(span, format!("'{}' is a function, not a variable", name));
+                None
+            }
+        }
+    }
+
+    /// The type of the element `array[index]`, `index_type` being the type of `index`
+    fn element(
+        &mut self,
+        array: &str,
+        index: &Expr,
+        index_type: Option<Type>,
+        span: Span,
+    ) -> Option<Type> {
+        if let Some(ty) = self.value(index_type, index) {
+            if ty != Type::Int {
+                let message = format!("Array index has to be an Int, found {:?}", ty);
+                self.error(index.span, message);
+            }
+        }
+        match self.symbols.lookup(array)? {
+            SymbolInfo::Variable {
+                ty,
+                array_size: Some(_),
+                ..
+            } => Some(ty),
+            _ => {
+                self.error(span, format!("'{}' is not an array", array));
+                None
+            }
+        }
+    }
+
+    /// The type `ty` of an expression that has to produce a value
+    fn value(&mut self, ty: Option<Type>, expr: &Expr) -> Option<Type> {
+        if let ExprKind::String(_) = expr.kind {
+            let message = "String literals are only allowed in printf".to_string();
+            self.error(expr.span, message);
+            return None;
+        }
+        match ty? {
+            Type::Void => {
+                self.error(expr.span, "A void call has no value".to_string());
+                None
+            }
+            ty => Some(ty),
+        }
+    }
+
+    /// The type of an expression, whose operands are checked already and have their types on
+    /// `types`. A string literal has no type, it is reported by `value` if it is not allowed.
+    fn expr(&mut self, expr: &Expr) -> Option<Type> {
+        match &expr.kind {
+            ExprKind::Int(_) | ExprKind::Char(_) => Some(Type::Int),
+            ExprKind::Float(_) => Some(Type::Float),
+            ExprKind::Bool(_) => Some(Type::Bool),
+            ExprKind::String(_) => None,
+            ExprKind::Variable(name) => self.variable(name, expr.span),
+            ExprKind::Index { array, index } => {
+                let index_type = self.pop(1)[0];
+                self.element(array, index, index_type, expr.span)
+            }
+            ExprKind::Call { name, arguments } => {
+                let types = self.pop(arguments.len());
+                let types = arguments
+                    .iter()
+                    .zip(types)
+                    .map(|(argument, ty)| self.value(ty, argument))
+                    .collect::<Vec<_>>();
+                let Some(SymbolInfo::Function {
+                    return_type,
+                    parameters,
+                    ..
+                }) = self.symbols.lookup(name)
+                else {
+                    if let Some(SymbolInfo::Variable { .. }) = self.symbols.lookup(name) {
+                        self.error(expr.span, format!("'{}' is not a function", name));
+                    }
+                    return None;
+                };
+                if parameters.len() != arguments.len() {
+                    let message = format!(
+                        "Function '{}' takes {} arguments but {} were given",
+                        name,
+                        parameters.len(),
+                        arguments.len()
+                    );
+                    self.error(expr.span, message);
+                } else {
+                    for ((expected, ty), argument) in parameters.iter().zip(types).zip(arguments) {
+                        if let Some(ty) = ty {
+                            self.expect(*expected, ty, argument);
+                        }
+                    }
+                }
+                Some(return_type)
+            }
+            ExprKind::Unary { op, operand } => {
+                let ty = self.pop(1)[0];
+                let ty = self.value(ty, operand)?;
+                let valid = match op {
+                    UnaryOp::Neg | UnaryOp::Plus => ty != Type::Bool,
+                    UnaryOp::Not => ty == Type::Bool,
+                };
+                if !valid {
+                    self.error(expr.span, format!("Cannot apply {:?} to {:?}", op, ty));
+                    return None;
+                }
+                Some(ty)
+            }
+            ExprKind::Binary { op, left, right } => {
+                let types = self.pop(2);
+                let (left, right) = (self.value(types[0], left)?, self.value(types[1], right)?);
+                let ty = self.binary(*op, left, right, expr.span)?;
+                if *op == BinaryOp::Div {
+                    let tag = match ty {
+                        Type::Int => BinaryOp::IntDiv,
+                        _ => BinaryOp::FloatDiv,
+                    };
+                    self.divisions.push((expr.span, tag));
+                }
+                Some(ty)
+            }
+            ExprKind::Conditional {
+                condition,
+                then_value,
+                else_value,
+            } => {
+                let types = self.pop(3);
+                self.condition(types[0], condition);
+                let then_type = self.value(types[1], then_value)?;
+                let else_type = self.value(types[2], else_value)?;
+                match (then_type, else_type) {
+                    (a, b) if a == b => Some(a),
+                    (Type::Int | Type::Float, Type::Int | Type::Float) => Some(Type::Float),
+                    _ => {
+                        let message = format!(
+                            "Branches have different types {:?} and {:?}",
+                            then_type, else_type
+                        );
+                        self.error(expr.span, message);
+                        None
+                    }
+                }
+            }
+            ExprKind::Assign { target, value } => {
+                let ty = self.pop(1)[0];
+                let target_type = self.variable(target, expr.span);
+                let value_type = self.value(ty, value)?;
+                self.expect(target_type?, value_type, value);
+                target_type
+            }
+        }
+    }
+
+    /// The result type of a binary operator, reporting operand types it does not accept
+    fn binary(&mut self, op: BinaryOp, left: Type, right: Type, span: Span) -> Option<Type> {
+        use BinaryOp::*;
+
+        let numeric = |ty| matches!(ty, Type::Int | Type::Float);
+        let result = match op {
+            Add | Sub | Mul | Div | IntDiv | FloatDiv if numeric(left) && numeric(right) => {
+                match (left, right) {
+                    (Type::Int, Type::Int) => Some(Type::Int),
+                    _ => Some(Type::Float),
+                }
+            }
+            Rem | BitAnd | BitOr | BitXor | ShiftLeft | ShiftRight
+                if left == Type::Int && right == Type::Int =>
+            {
+                Some(Type::Int)
+            }
+            Less | Greater | LessEqual | GreaterEqual if numeric(left) && numeric(right) => {
+                Some(Type::Bool)
+            }
+            Equal | NotEqual if numeric(left) == numeric(right) => Some(Type::Bool),
+            And | Or if left == Type::Bool && right == Type::Bool => Some(Type::Bool),
+            _ => None,
+        };
+        if result.is_none() {
+            let message = format!("Cannot apply {:?} to {:?} and {:?}", op, left, right);
+            self.error(span, message);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{BinaryOp, ExprKind, Item, Span, StmtKind};
+    use crate::typecheck::{tag_divisions, typecheck};
+    use crate::C1Parser;
+
+    fn errors(text: &str) -> Vec<String> {
+        let program = C1Parser::parse_to_ast(text).unwrap();
+        match typecheck(&program) {
+            Ok(()) => vec![],
+            Err(errors) => errors.into_iter().map(|error| error.message).collect(),
+        }
+    }
+
+    #[test]
+    fn valid_program() {
+        let text = "
+            int a[3];
+            float half(int x) { return x / 2.0; }
+            bool even(int x) { return x % 2 == 0; }
+            void main() {
+                float f = 1;
+                int i;
+                for (i = 0; i < 3; i += 1) {
+                    if (even(i) && !(half(i) > 1)) { f += half(a[i]); }
+                }
+                printf(\"%f\", f < 1 ? 1 : f);
+            }";
+        assert_eq!(errors(text), Vec::<String>::new());
+    }
+
+    #[test]
+    fn float_to_int() {
+        let text = "void f() { int x = 1.5; }";
+        let program = C1Parser::parse_to_ast(text).unwrap();
+        let found = typecheck(&program).unwrap_err();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].span, Span { start: 19, end: 22 });
+
+        assert_eq!(errors("void f() { int x; x = 2.0; }").len(), 1);
+        assert_eq!(errors("int f() { return 2.0; }").len(), 1);
+        assert_eq!(errors("void g(int x) {} void f() { g(1.0); }").len(), 1);
+    }
+
+    #[test]
+    fn int_condition() {
+        let text = "void f() { int x; if (x) {} }";
+        let program = C1Parser::parse_to_ast(text).unwrap();
+        let found = typecheck(&program).unwrap_err();
+        assert_eq!(found.len(), 1);
+        assert_eq!(&text[found[0].span.start..found[0].span.end], "x");
+
+        assert_eq!(errors("void f() { while (1) {} }").len(), 1);
+        assert_eq!(errors("void f() { bool b = 1 ? true : false; }").len(), 1);
+    }
+
+    #[test]
+    fn operands() {
+        assert_eq!(errors("void f() { int x = true + 1; }").len(), 1);
+        assert_eq!(errors("void f() { float x = 1.5 % 2; }").len(), 1);
+        assert_eq!(errors("void f() { bool b = 1 && true; }").len(), 1);
+        assert_eq!(errors("void f() { bool b = !1; }").len(), 1);
+        assert_eq!(errors("void f() { bool b = true == 1; }").len(), 1);
+        assert_eq!(errors("void f() { bool b; b++; }").len(), 1);
+        assert!(errors("void f() { int a[3]; a[2] = 1; a[0] += a[1]; }").is_empty());
+        assert_eq!(errors("void f() { int a[3]; a[0] = true; }").len(), 1);
+        assert_eq!(errors("void f() { int a[3]; a[1.5] = 1; }").len(), 1);
+        assert_eq!(
+            errors("void f() { int x; x[0] = 1; }"),
+            ["'x' is not an array"]
+        );
+        assert_eq!(errors("void g() {} void f() { int x = g(); }").len(), 1);
+        assert_eq!(errors("int f() { return; }").len(), 1);
+        assert_eq!(errors("void f() { return 1; }").len(), 1);
+        // the loop variable is in scope in the whole loop, and only there
+        assert!(errors("void f() { for (int i = 0; i < 3; i += 1) { i = 2; } }").is_empty());
+        assert_eq!(errors("void f() { for (bool b = 1; b; ) {} }").len(), 1);
+        assert!(errors("void f() { bool i; for (int i = 0; i < 3; ) {} i = true; }").is_empty());
+        // an error is only reported once
+        assert_eq!(errors("void f() { int x = (true + 1) * 2 - 3; }").len(), 1);
+    }
+
+    /// The operator of the tagged `/` in the expression statement `f(value);`
+    fn division(value: &str) -> BinaryOp {
+        let text = format!("void f(float v) {{ int i; float x; f({}); }}", value);
+        let mut program = C1Parser::parse_to_ast(&text).unwrap();
+        tag_divisions(&mut program);
+        let Item::Function(function) = &program.items[0] else {
+            panic!("not a function");
+        };
+        let StmtKind::Expr(call) = &function.body[2].kind else {
+            panic!("not an expression statement");
+        };
+        let ExprKind::Call { arguments, .. } = &call.kind else {
+            panic!("not a call");
+        };
+        match arguments[0].kind {
+            ExprKind::Binary { op, .. } => op,
+            _ => panic!("not a binary expression"),
+        }
+    }
+
+    #[test]
+    fn tagged_divisions() {
+        assert_eq!(division("5 / 2"), BinaryOp::IntDiv);
+        assert_eq!(division("5.0 / 2"), BinaryOp::FloatDiv);
+        assert_eq!(division("i / x"), BinaryOp::FloatDiv);
+        assert_eq!(division("i / 2 / i"), BinaryOp::IntDiv);
+        // unknown or invalid operands are left alone
+        assert_eq!(division("y / 2"), BinaryOp::Div);
+        assert_eq!(division("true / 2"), BinaryOp::Div);
+    }
+}
@@ -0,0 +1,483 @@
+use crate::ast::{
+    Declaration, Expr, ExprKind, Function, Item, Parameter, Program, Stmt, StmtKind, Variable,
+};
+
+/// Read-only traversal of an AST. Every hook does nothing by default, so an implementation only
+/// overrides the node kinds it is interested in. The matching `walk_*` function calls `visit_*`
+/// on a node, walks its children in source order and then calls `leave_*`.
+///
+/// Blocks and `for` loops are scopes of their own: `enter_scope` is called before their children
+/// are walked and `leave_scope` after. A function is the scope of its parameters and body, it is
+/// entered by `visit_function` and left by `leave_function`.
+///
+/// ```
+/// use cb_3::ast::{Expr, ExprKind};
+/// use cb_3::visitor::{walk_program, Visitor};
+/// use cb_3::C1Parser;
+///
+/// struct Calls(usize);
+///
+/// impl Visitor for Calls {
+///     fn visit_expr(&mut self, expr: &Expr) {
+///         if let ExprKind::Call { .. } = expr.kind {
+///             self.0 += 1;
+///         }
+///     }
+/// }
+///
+/// let program = C1Parser::parse_to_ast("void f() { f(); g(); }").unwrap();
+/// let mut calls = Calls(0);
+/// walk_program(&mut calls, &program);
+/// assert_eq!(calls.0, 2);
+/// ```
+pub trait Visitor {
+    fn visit_program(&mut self, _program: &Program) {}
+    fn leave_program(&mut self, _program: &Program) {}
+    fn visit_item(&mut self, _item: &Item) {}
+    fn leave_item(&mut self, _item: &Item) {}
+    fn visit_function(&mut self, _function: &Function) {}
+    fn leave_function(&mut self, _function: &Function) {}
+    fn visit_parameter(&mut self, _parameter: &Parameter) {}
+    fn visit_declaration(&mut self, _declaration: &Declaration) {}
+    fn leave_declaration(&mut self, _declaration: &Declaration) {}
+    fn visit_variable(&mut self, _variable: &Variable) {}
+    fn leave_variable(&mut self, _variable: &Variable) {}
+    fn visit_stmt(&mut self, _stmt: &Stmt) {}
+    fn leave_stmt(&mut self, _stmt: &Stmt) {}
+    fn visit_expr(&mut self, _expr: &Expr) {}
+    fn leave_expr(&mut self, _expr: &Expr) {}
+    fn enter_scope(&mut self) {}
+    fn leave_scope(&mut self) {}
+}
+
+pub fn walk_program<V: Visitor + ?Sized>(visitor: &mut V, program: &Program) {
+    visitor.visit_program(program);
+    for item in &program.items {
+        walk_item(visitor, item);
+    }
+    visitor.leave_program(program);
+}
+
+pub fn walk_item<V: Visitor + ?Sized>(visitor: &mut V, item: &Item) {
+    visitor.visit_item(item);
+    match item {
+        Item::Function(function) => walk_function(visitor, function),
+        Item::Global(declaration) => walk_declaration(visitor, declaration),
+    }
+    visitor.leave_item(item);
+}
+
+pub fn walk_function<V: Visitor + ?Sized>(visitor: &mut V, function: &Function) {
+    visitor.visit_function(function);
+    for parameter in &function.parameters {
+        visitor.visit_parameter(parameter);
+    }
+    for stmt in &function.body {
+        walk_stmt(visitor, stmt);
+    }
+    visitor.leave_function(function);
+}
+
+pub fn walk_declaration<V: Visitor + ?Sized>(visitor: &mut V, declaration: &Declaration) {
+    visitor.visit_declaration(declaration);
+    for variable in &declaration.variables {
+        walk_variable(visitor, variable);
+    }
+    visitor.leave_declaration(declaration);
+}
+
+pub fn walk_variable<V: Visitor + ?Sized>(visitor: &mut V, variable: &Variable) {
+    visitor.visit_variable(variable);
+    if let Some(initializer) = &variable.initializer {
+        walk_expr(visitor, initializer);
+    }
+    visitor.leave_variable(variable);
+}
+
+pub fn walk_stmt<V: Visitor + ?Sized>(visitor: &mut V, stmt: &Stmt) {
+    visitor.visit_stmt(stmt);
+    match &stmt.kind {
+        StmtKind::Block(statements) => {
+            visitor.enter_scope();
+            for stmt in statements {
+                walk_stmt(visitor, stmt);
+            }
+            visitor.leave_scope();
+        }
+        StmtKind::Declaration(declaration) => walk_declaration(visitor, declaration),
+        StmtKind::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => {
+            walk_expr(visitor, condition);
+            walk_stmt(visitor, then_branch);
+            if let Some(else_branch) = else_branch {
+                walk_stmt(visitor, else_branch);
+            }
+        }
+        StmtKind::While { condition, body } => {
+            walk_expr(visitor, condition);
+            walk_stmt(visitor, body);
+        }
+        StmtKind::DoWhile { body, condition } => {
+            walk_stmt(visitor, body);
+            walk_expr(visitor, condition);
+        }
+        StmtKind::For {
+            init,
+            condition,
+            update,
+            body,
+        } => {
+            visitor.enter_scope();
+            if let Some(init) = init {
+                walk_stmt(visitor, init);
+            }
+            walk_expr(visitor, condition);
+            if let Some(update) = update {
+                walk_stmt(visitor, update);
+            }
+            walk_stmt(visitor, body);
+            visitor.leave_scope();
+        }
+        StmtKind::Return(value) => {
+            if let Some(value) = value {
+                walk_expr(visitor, value);
+            }
+        }
+        StmtKind::Printf(arguments) => {
+            for argument in arguments {
+                walk_expr(visitor, argument);
+            }
+        }
+        StmtKind::Assign { index, value, .. } => {
+            if let Some(index) = index {
+                walk_expr(visitor, index);
+            }
+            walk_expr(visitor, value);
+        }
+        StmtKind::Expr(value) => walk_expr(visitor, value),
+        StmtKind::Empty
+        | StmtKind::Break
+        | StmtKind::Continue
+        | StmtKind::Asm(_)
+        | StmtKind::IncDec { .. } => {}
+    }
+    visitor.leave_stmt(stmt);
+}
+
+pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, expr: &Expr) {
+    visitor.visit_expr(expr);
+    match &expr.kind {
+        ExprKind::Index { index: operand, .. }
+        | ExprKind::Unary { operand, .. }
+        | ExprKind::Assign { value: operand, .. } => walk_expr(visitor, operand),
+        ExprKind::Binary { left, right, .. } => {
+            walk_expr(visitor, left);
+            walk_expr(visitor, right);
+        }
+        ExprKind::Call { arguments, .. } => {
+            for argument in arguments {
+                walk_expr(visitor, argument);
+            }
+        }
+        ExprKind::Conditional {
+            condition,
+            then_value,
+            else_value,
+        } => {
+            walk_expr(visitor, condition);
+            walk_expr(visitor, then_value);
+            walk_expr(visitor, else_value);
+        }
+        ExprKind::Int(_)
+        | ExprKind::Float(_)
+        | ExprKind::Bool(_)
+        | ExprKind::Char(_)
+        | ExprKind::String(_)
+        | ExprKind::Variable(_) => {}
+    }
+    visitor.leave_expr(expr);
+}
+
+/// Like `Visitor`, but the hooks may change the nodes they are given. A node can be replaced
+/// in `leave_*`, after its children were walked, without the walk descending into the
+/// replacement.
+pub trait VisitorMut {
+    fn visit_program(&mut self, _program: &mut Program) {}
+    fn leave_program(&mut self, _program: &mut Program) {}
+    fn visit_item(&mut self, _item: &mut Item) {}
+    fn leave_item(&mut self, _item: &mut Item) {}
+    fn visit_function(&mut self, _function: &mut Function) {}
+    fn leave_function(&mut self, _function: &mut Function) {}
+    fn visit_parameter(&mut self, _parameter: &mut Parameter) {}
+    fn visit_declaration(&mut self, _declaration: &mut Declaration) {}
+    fn leave_declaration(&mut self, _declaration: &mut Declaration) {}
+    fn visit_variable(&mut self, _variable: &mut Variable) {}
+    fn leave_variable(&mut self, _variable: &mut Variable) {}
+    fn visit_stmt(&mut self, _stmt: &mut Stmt) {}
+    fn leave_stmt(&mut self, _stmt: &mut Stmt) {}
+    fn visit_expr(&mut self, _expr: &mut Expr) {}
+    fn leave_expr(&mut self, _expr: &mut Expr) {}
+    fn enter_scope(&mut self) {}
+    fn leave_scope(&mut self) {}
+}
+
+pub fn walk_program_mut<V: VisitorMut + ?Sized>(visitor: &mut V, program: &mut Program) {
+    visitor.visit_program(program);
+    for item in &mut program.items {
+        walk_item_mut(visitor, item);
+    }
+    visitor.leave_program(program);
+}
+
+pub fn walk_item_mut<V: VisitorMut + ?Sized>(visitor: &mut V, item: &mut Item) {
+    visitor.visit_item(item);
+    match item {
+        Item::Function(function) => walk_function_mut(visitor, function),
+        Item::Global(declaration) => walk_declaration_mut(visitor, declaration),
+    }
+    visitor.leave_item(item);
+}
+
+pub fn walk_function_mut<V: VisitorMut + ?Sized>(visitor: &mut V, function: &mut Function) {
+    visitor.visit_function(function);
+    for parameter in &mut function.parameters {
+        visitor.visit_parameter(parameter);
+    }
+    for stmt in &mut function.body {
+        walk_stmt_mut(visitor, stmt);
+    }
+    visitor.leave_function(function);
+}
+
+pub fn walk_declaration_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    declaration: &mut Declaration,
+) {
+    visitor.visit_declaration(declaration);
+    for variable in &mut declaration.variables {
+        walk_variable_mut(visitor, variable);
+    }
+    visitor.leave_declaration(declaration);
+}
+
+pub fn walk_variable_mut<V: VisitorMut + ?Sized>(visitor: &mut V, variable: &mut Variable) {
+    visitor.visit_variable(variable);
+    if let Some(initializer) = &mut variable.initializer {
+        walk_expr_mut(visitor, initializer);
+    }
+    visitor.leave_variable(variable);
+}
+
+pub fn walk_stmt_mut<V: VisitorMut + ?Sized>(visitor: &mut V, stmt: &mut Stmt) {
+    visitor.visit_stmt(stmt);
+    match &mut stmt.kind {
+        StmtKind::Block(statements) => {
+            visitor.enter_scope();
+            for stmt in statements {
+                walk_stmt_mut(visitor, stmt);
+            }
+            visitor.leave_scope();
+        }
+        StmtKind::Declaration(declaration) => walk_declaration_mut(visitor, declaration),
+        StmtKind::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => {
+            walk_expr_mut(visitor, condition);
+            walk_stmt_mut(visitor, then_branch);
+            if let Some(else_branch) = else_branch {
+                walk_stmt_mut(visitor, else_branch);
+            }
+        }
+        StmtKind::While { condition, body } => {
+            walk_expr_mut(visitor, condition);
+            walk_stmt_mut(visitor, body);
+        }
+        StmtKind::DoWhile { body, condition } => {
+            walk_stmt_mut(visitor, body);
+            walk_expr_mut(visitor, condition);
+        }
+        StmtKind::For {
+            init,
+            condition,
+            update,
+            body,
+        } => {
+            visitor.enter_scope();
+            if let Some(init) = init {
+                walk_stmt_mut(visitor, init);
+            }
+            walk_expr_mut(visitor, condition);
+            if let Some(update) = update {
+                walk_stmt_mut(visitor, update);
+            }
+            walk_stmt_mut(visitor, body);
+            visitor.leave_scope();
+        }
+        StmtKind::Return(value) => {
+            if let Some(value) = value {
+                walk_expr_mut(visitor, value);
+            }
+        }
+        StmtKind::Printf(arguments) => {
+            for argument in arguments {
+                walk_expr_mut(visitor, argument);
+            }
+        }
+        StmtKind::Assign { index, value, .. } => {
+            if let Some(index) = index {
+                walk_expr_mut(visitor, index);
+            }
+            walk_expr_mut(visitor, value);
+        }
+        StmtKind::Expr(value) => walk_expr_mut(visitor, value),
+        StmtKind::Empty
+        | StmtKind::Break
+        | StmtKind::Continue
+        | StmtKind::Asm(_)
+        | StmtKind::IncDec { .. } => {}
+    }
+    visitor.leave_stmt(stmt);
+}
+
+pub fn walk_expr_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expr: &mut Expr) {
+    visitor.visit_expr(expr);
+    match &mut expr.kind {
+        ExprKind::Index { index: operand, .. }
+        | ExprKind::Unary { operand, .. }
+        | ExprKind::Assign { value: operand, .. } => walk_expr_mut(visitor, operand),
+        ExprKind::Binary { left, right, .. } => {
+            walk_expr_mut(visitor, left);
+            walk_expr_mut(visitor, right);
+        }
+        ExprKind::Call { arguments, .. } => {
+            for argument in arguments {
+                walk_expr_mut(visitor, argument);
+            }
+        }
+        ExprKind::Conditional {
+            condition,
+            then_value,
+            else_value,
+        } => {
+            walk_expr_mut(visitor, condition);
+            walk_expr_mut(visitor, then_value);
+            walk_expr_mut(visitor, else_value);
+        }
+        ExprKind::Int(_)
+        | ExprKind::Float(_)
+        | ExprKind::Bool(_)
+        | ExprKind::Char(_)
+        | ExprKind::String(_)
+        | ExprKind::Variable(_) => {}
+    }
+    visitor.leave_expr(expr);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{Expr, ExprKind, Function, Stmt, StmtKind};
+    use crate::visitor::{walk_program, walk_program_mut, Visitor, VisitorMut};
+    use crate::C1Parser;
+
+    #[derive(Default)]
+    struct Returns(usize);
+
+    impl Visitor for Returns {
+        fn visit_stmt(&mut self, stmt: &Stmt) {
+            if let StmtKind::Return(_) = stmt.kind {
+                self.0 += 1;
+            }
+        }
+    }
+
+    #[test]
+    fn count_returns() {
+        let program = C1Parser::parse_to_ast(
+            "int f(int a) { if (a) { return 1; } else { while (a) { return 2; } } return 3; }
+            void g() { return; }
+            void h() { do { for (;a;) { return; } } while (a); }",
+        )
+        .unwrap();
+        let mut returns = Returns::default();
+        walk_program(&mut returns, &program);
+        assert_eq!(returns.0, 5);
+    }
+
+    /// Records the hooks in the order they are called
+    #[derive(Default)]
+    struct Events(Vec<String>);
+
+    impl Visitor for Events {
+        fn visit_function(&mut self, function: &Function) {
+            self.0.push(format!("fn {}", function.name));
+        }
+        fn leave_function(&mut self, function: &Function) {
+            self.0.push(format!("end {}", function.name));
+        }
+        fn leave_stmt(&mut self, stmt: &Stmt) {
+            if let StmtKind::Assign { target, .. } = &stmt.kind {
+                self.0.push(format!("assigned {}", target));
+            }
+        }
+        fn leave_expr(&mut self, expr: &Expr) {
+            if let ExprKind::Variable(name) = &expr.kind {
+                self.0.push(name.clone());
+            }
+        }
+        fn enter_scope(&mut self) {
+            self.0.push("{".to_string());
+        }
+        fn leave_scope(&mut self) {
+            self.0.push("}".to_string());
+        }
+    }
+
+    #[test]
+    fn leave_and_scope_hooks() {
+        let program =
+            C1Parser::parse_to_ast("void f() { a = b; { for (int i = 0; i < n; ) c = i; } }")
+                .unwrap();
+        let mut events = Events::default();
+        walk_program(&mut events, &program);
+        let expected = [
+            "fn f",
+            "b",
+            "assigned a",
+            "{",
+            "{",
+            "i",
+            "n",
+            "i",
+            "assigned c",
+            "}",
+            "}",
+            "end f",
+        ];
+        assert_eq!(events.0, expected);
+    }
+
+    struct Rename;
+
+    impl VisitorMut for Rename {
+        fn leave_expr(&mut self, expr: &mut Expr) {
+            if let ExprKind::Variable(name) = &mut expr.kind {
+                name.make_ascii_uppercase();
+            }
+        }
+    }
+
+    #[test]
+    fn change_nodes() {
+        let mut program = C1Parser::parse_to_ast("int x = a + f(b);").unwrap();
+        walk_program_mut(&mut Rename, &mut program);
+        assert_eq!(
+            program,
+            C1Parser::parse_to_ast("int x = A + f(B);").unwrap()
+        );
+    }
+}
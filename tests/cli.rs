@@ -0,0 +1,75 @@
+use std::process::{Command, Output};
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_cb-3"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn valid_file() {
+    let output = run(&["tests/data/beispiel.c-1"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "OK\n");
+}
+
+#[test]
+fn invalid_file() {
+    let output = run(&["tests/data/fehler.c-1"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.starts_with("tests/data/fehler.c-1:3:1: error: "),
+        "{}",
+        stderr
+    );
+    assert!(stderr.ends_with("3 | }\n  | ^\n"), "{}", stderr);
+
+    // every error, in the order of the file
+    let output = run(&["--all", "tests/data/fehler.c-1"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let locations: Vec<_> = stderr
+        .lines()
+        .filter_map(|line| line.strip_prefix("tests/data/fehler.c-1:"))
+        .map(|line| line.split(": ").next().unwrap())
+        .collect();
+    assert_eq!(locations, ["3:1", "6:7"]);
+}
+
+#[test]
+fn tokens() {
+    let output = run(&["--tokens", "tests/data/fehler.c-1"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.starts_with("1\tKwInt\tint\n1\tIdentifier\tf\n"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.ends_with("7\tRightBrace\t}\n"), "{}", stdout);
+}
+
+#[test]
+fn usage() {
+    assert_eq!(run(&[]).status.code(), Some(2));
+    assert_eq!(
+        run(&["--unknown", "tests/data/beispiel.c-1"]).status.code(),
+        Some(2)
+    );
+    assert_eq!(run(&["tests/data/missing.c-1"]).status.code(), Some(2));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn json() {
+    let output = run(&["--json", "tests/data/beispiel.c-1"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.starts_with(r#"{"items":[{"Function":{"#),
+        "{}",
+        stdout
+    );
+}
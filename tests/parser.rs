@@ -1,4 +1,5 @@
 use cb_3::C1Parser;
+use std::env;
 use std::fs;
 
 #[test]
@@ -7,3 +8,56 @@ fn run_example() {
     let result = C1Parser::parse(text.as_str());
     assert!(result.is_ok(), "Parse result: {}", result.err().unwrap());
 }
+
+#[test]
+fn parse_directory() {
+    let dir = env::temp_dir().join(format!("cb3-parse-dir-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("valid.c1"), "int f() { return 1; }").unwrap();
+    fs::write(dir.join("invalid.c1"), "int f() { return 1 }").unwrap();
+    fs::write(dir.join("ignored.txt"), "not a program").unwrap();
+
+    let results = C1Parser::parse_dir(&dir);
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].0, dir.join("invalid.c1"));
+    let err = results[0].1.as_ref().unwrap_err();
+    let prefix = format!("{}: ", dir.join("invalid.c1").display());
+    assert!(err.message.starts_with(&prefix), "{}", err);
+    assert_eq!((err.line, err.column), (1, Some(20)));
+    assert_eq!(results[1].0, dir.join("valid.c1"));
+    assert_eq!(results[1].1, Ok(()));
+
+    let results = C1Parser::parse_dir(&dir);
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].0, dir);
+    let err = results[0].1.as_ref().unwrap_err();
+    assert!(
+        err.message.starts_with(&format!("{}: ", dir.display())),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn parse_file() {
+    let dir = env::temp_dir().join(format!("cb3-parse-file-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("valid.c1"), "int f() { return 1; }").unwrap();
+    fs::write(dir.join("invalid.c1"), "int f() {\n  return 1\n}").unwrap();
+
+    let valid = C1Parser::parse_file(dir.join("valid.c1"));
+    let invalid = C1Parser::parse_file(dir.join("invalid.c1"));
+    let missing = C1Parser::parse_file(dir.join("missing.c1"));
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(valid, Ok(()));
+    let err = invalid.unwrap_err();
+    let prefix = format!("{}: ", dir.join("invalid.c1").display());
+    assert!(err.message.starts_with(&prefix), "{}", err);
+    assert_eq!((err.line, err.column), (3, Some(1)));
+    let err = missing.unwrap_err();
+    assert!(err.message.contains("missing.c1"), "{}", err);
+    assert_eq!((err.line, err.found), (1, None));
+}